@@ -14,3 +14,5 @@ cargo run -- examples/check-panic/detected.rs --crate-type=lib 2>examples/check-
 cargo install --path . --locked --debug
 cd tests/vec-push
 cargo redpen
+cd ../workspace
+cargo redpen -- --workspace --exclude app
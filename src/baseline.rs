@@ -0,0 +1,64 @@
+use crate::report::Finding;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Known findings written by `cargo redpen --write-baseline`, which are not reported again
+/// by `--baseline`. Findings are matched by [`Finding::id`], so they stay known when code
+/// moves, but are reported once they reach new callees.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Baseline {
+    /// Sorted by id.
+    findings: Vec<Known>,
+}
+
+/// A known finding. Only the id is matched; the rest helps reviewing the baseline file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Known {
+    id: String,
+    function: String,
+    title: String,
+}
+
+impl Baseline {
+    pub fn new<'a>(findings: impl IntoIterator<Item = &'a Finding>) -> Self {
+        let findings = findings
+            .into_iter()
+            .map(|f| Known {
+                id: f.id.clone(),
+                function: f.function.clone(),
+                title: f.title.clone(),
+            })
+            .collect();
+        Baseline::sorted(findings)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let baseline: Baseline = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+        Ok(Baseline::sorted(baseline.findings))
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap() + "\n")
+    }
+
+    pub fn len(&self) -> usize {
+        self.findings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    pub fn contains(&self, finding: &Finding) -> bool {
+        self.findings
+            .binary_search_by(|known| known.id.cmp(&finding.id))
+            .is_ok()
+    }
+
+    fn sorted(mut findings: Vec<Known>) -> Self {
+        findings.sort_by(|k1, k2| k1.id.cmp(&k2.id));
+        findings.dedup_by(|k1, k2| k1.id == k2.id);
+        Baseline { findings }
+    }
+}
@@ -0,0 +1,139 @@
+use redpen::{baseline::Baseline, options::Check, report::CrateReport};
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
+
+/// A crate cargo has checked, from its `compiler-artifact` message.
+pub struct Artifact {
+    pub package_id: String,
+    pub filenames: Vec<PathBuf>,
+}
+
+/// Results of the crates cargo has checked, fresh ones included, sorted by crate name
+/// rather than the order cargo reports their artifacts, which varies between runs.
+///
+/// Dependencies have no results when they are summarized or compiled, but crates of the
+/// `primary` packages must, or redpen would silently report nothing for them. The error
+/// lists the artifacts of those without results.
+pub fn load(
+    results_dir: &Path,
+    artifacts: &[Artifact],
+    primary: &HashSet<String>,
+) -> Result<Vec<CrateReport>, Vec<PathBuf>> {
+    let mut loaded = BTreeSet::new();
+    let mut missing = Vec::new();
+    let mut reports: Vec<CrateReport> = Vec::new();
+    let rmetas = artifacts.iter().flat_map(|artifact| {
+        let filenames = artifact.filenames.iter();
+        filenames.map(|filename| (primary.contains(&artifact.package_id), filename))
+    });
+    for (primary, artifact) in rmetas {
+        // The driver names results after the `.rmeta` file, like `libutils-<hash>.rmeta`.
+        if artifact.extension().is_none_or(|ext| ext != "rmeta") {
+            continue;
+        }
+        let Some(stem) = artifact.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let name = stem.strip_prefix("lib").unwrap_or(stem);
+        let path = results_dir.join(format!("{name}.json"));
+        if !loaded.insert(path.clone()) {
+            continue;
+        }
+        let Ok(json) = std::fs::read_to_string(&path) else {
+            if primary {
+                missing.push(artifact.clone());
+            }
+            continue;
+        };
+        match serde_json::from_str(&json) {
+            Ok(report) => reports.push(report),
+            Err(err) => eprintln!(
+                "warning: invalid redpen results `{}`: {err}",
+                path.display()
+            ),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+    reports.sort_by(|r1, r2| (&r1.krate, r1.test).cmp(&(&r2.krate, r2.test)));
+    Ok(reports)
+}
+
+/// Remove findings known in the baseline, returning how many are removed.
+pub fn remove_known(reports: &mut [CrateReport], baseline: &Baseline) -> usize {
+    let mut known = 0;
+    for report in reports {
+        let before = report.findings.len();
+        report.findings.retain(|f| !baseline.contains(f));
+        known += before - report.findings.len();
+    }
+    known
+}
+
+/// Print a table of analyzed entries, functions with findings, and findings by check.
+pub fn print_table(reports: &[CrateReport], checks: &[Check]) {
+    let mut header = vec![
+        "Crate".to_owned(),
+        "Entries".to_owned(),
+        "Functions".to_owned(),
+    ];
+    header.extend(checks.iter().map(|c| c.name().to_owned()));
+
+    let count = |report: &CrateReport| -> Vec<usize> {
+        let functions: BTreeSet<_> = report.findings.iter().map(|f| &f.function).collect();
+        let mut counts = vec![report.entries, functions.len()];
+        counts.extend(
+            checks
+                .iter()
+                .map(|&c| report.findings.iter().filter(|f| f.check == c).count()),
+        );
+        counts
+    };
+    let name = |report: &CrateReport| {
+        if report.test {
+            format!("{} (test)", report.krate)
+        } else {
+            report.krate.clone()
+        }
+    };
+    let mut rows: Vec<(String, Vec<usize>)> = reports.iter().map(|r| (name(r), count(r))).collect();
+    let total = rows
+        .iter()
+        .fold(vec![0; header.len() - 1], |mut total, (_, counts)| {
+            total.iter_mut().zip(counts).for_each(|(t, c)| *t += c);
+            total
+        });
+    rows.push(("Total".to_owned(), total));
+
+    let mut widths: Vec<usize> = header.iter().map(String::len).collect();
+    for (krate, counts) in &rows {
+        widths[0] = widths[0].max(krate.len());
+        for (width, count) in widths[1..].iter_mut().zip(counts) {
+            *width = (*width).max(count.to_string().len());
+        }
+    }
+
+    let line = |first: &str, rest: Vec<String>| {
+        let mut line = format!("{first:<0$}", widths[0]);
+        for (cell, width) in rest.iter().zip(&widths[1..]) {
+            line.push_str(&format!("  {cell:>width$}"));
+        }
+        eprintln!("{}", line.trim_end());
+    };
+    line(&header[0], header[1..].to_vec());
+    for (krate, counts) in rows {
+        line(&krate, counts.iter().map(usize::to_string).collect());
+    }
+}
+
+/// Write results of all crates to one JSON file.
+pub fn write_merged(path: &Path, reports: &[CrateReport]) {
+    let json = serde_json::to_string_pretty(reports).unwrap();
+    if let Err(err) = std::fs::write(path, json) {
+        eprintln!("error: failed to write `{}`: {err}", path.display());
+        std::process::exit(1);
+    }
+}
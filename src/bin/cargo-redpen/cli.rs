@@ -0,0 +1,330 @@
+use redpen::options::{Check, Entries, Format, GraphFormat, Options};
+use std::path::PathBuf;
+
+pub const HELP: &str = "\
+Check Rust crates for possible panics and other hazards with redpen.
+
+Usage: cargo redpen [COMMAND] [OPTIONS] [-- CARGO_OPTIONS...]
+
+Commands:
+  check    Analyze the selected packages and print diagnostics [default]
+  report   Analyze like `check`, and also write findings to target/redpen/report
+  explain  Describe a check, or list all checks if none is given
+  clean    Remove summaries, cached findings and reports under target/redpen
+  diff     Compare two reports, like `cargo redpen diff old.json new.json`, and fail if
+           findings are added
+
+Options:
+  --config <PATH>    Config file to use [default: redpen.toml if present]
+  --checks <CHECKS>  Comma-separated checks to run
+                     [default: all but stack-depth and panics-doc]
+  --format <FORMAT>  Print findings as `human` diagnostics, `json` lines, or `os-checker`
+                     JSON lines with file, line, kind, message and raw text [default: human]
+  --entries <MODE>   Functions to report: `all`, `pub` API, `exports` like `main`,
+                     `#[test]` and `#[no_mangle]`, or `tagged` `#[redpen::entry]` [default: all]
+  --skip-tests       Don't report `#[test]` functions, only helpers they call
+  --baseline <FILE>  Don't report findings known in the baseline file
+  --write-baseline <FILE>
+                     Record all current findings to the baseline file
+  --graph <FORMAT>   Also write call graphs to target/redpen/graph in `dot`, `json` or
+                     `graphml` format
+  --graph-entry <PATH>
+                     Only write the subgraph from this function to panics, e.g. `utils::parse`
+  --root-causes      Report each spot once where it calls into a panic or forbidden
+                     function, listing entries reaching it, instead of every caller
+  --no-summarize-deps
+                     Don't summarize dependencies, so that analyzed crates walk their bodies;
+                     otherwise `recursion`, `locks` and `stack-depth` stop at them
+  -h, --help         Print help
+  -V, --version      Print version
+
+Target selection:
+  --tests            Also analyze tests, with `#[test]` functions as entries
+  --examples         Also analyze examples
+  --benches          Also analyze benches
+  --all-targets      Analyze all targets, like `--tests --examples --benches`
+  --target <TRIPLE>  Check for the target triple, e.g. `thumbv7em-none-eabihf`
+
+Arguments after `--` are passed to `cargo check`, e.g.
+  cargo redpen -- -p utils
+  cargo redpen report --checks panic,locks -- --workspace --exclude app
+  cargo redpen --target thumbv7em-none-eabihf -- -Zbuild-std=core
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Check,
+    Report,
+    Explain,
+    Clean,
+    Diff,
+}
+
+#[derive(Debug)]
+pub struct Cli {
+    pub command: Command,
+    /// The check to explain.
+    pub explain: Option<Check>,
+    /// Old and new reports to compare.
+    pub diff: Vec<PathBuf>,
+    /// Where to record findings as a baseline.
+    pub write_baseline: Option<PathBuf>,
+    /// Run the driver on dependencies to summarize them, unless `--no-summarize-deps`.
+    pub summarize_deps: bool,
+    /// Options for the driver.
+    pub options: Options,
+    /// Arguments after `--`, passed to cargo.
+    pub cargo_args: Vec<String>,
+}
+
+/// What to do after parsing the command line.
+#[derive(Debug)]
+pub enum Parsed {
+    Run(Box<Cli>),
+    Help,
+    Version,
+}
+
+impl Cli {
+    /// Parse arguments following `cargo redpen`.
+    pub fn parse(args: &[String]) -> Result<Parsed, String> {
+        let (args, cargo_args) = match args.iter().position(|arg| arg == "--") {
+            Some(idx) => (&args[..idx], args[idx + 1..].to_vec()),
+            None => (args, Vec::new()),
+        };
+        let mut cli = Cli {
+            command: Command::Check,
+            explain: None,
+            diff: Vec::new(),
+            write_baseline: None,
+            summarize_deps: true,
+            options: Options::default(),
+            cargo_args,
+        };
+
+        let mut args = args.iter();
+        let mut has_command = false;
+        while let Some(arg) = args.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_owned())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next().cloned())
+                    .ok_or_else(|| format!("`{name}` requires a value"))
+            };
+            match name {
+                "-h" | "--help" => return Ok(Parsed::Help),
+                "-V" | "--version" => return Ok(Parsed::Version),
+                "--config" => cli.options.config = Some(value()?.into()),
+                "--checks" => cli.options.checks = parse_checks(&value()?)?,
+                "--format" => {
+                    let format = value()?;
+                    cli.options.format = Format::from_name(&format).ok_or_else(|| {
+                        format!(
+                            "unknown format `{format}`; expected `human`, `json` or `os-checker`"
+                        )
+                    })?;
+                }
+                "--entries" => {
+                    let entries = value()?;
+                    cli.options.entries = Entries::from_name(&entries).ok_or_else(|| {
+                        let names: Vec<_> = Entries::ALL.iter().map(|e| e.name()).collect();
+                        format!(
+                            "unknown entries `{entries}`; expected one of {}",
+                            names.join(", ")
+                        )
+                    })?;
+                }
+                "--skip-tests" => cli.options.skip_tests = true,
+                "--baseline" => cli.options.baseline = Some(value()?.into()),
+                "--write-baseline" => cli.write_baseline = Some(value()?.into()),
+                "--graph" => {
+                    let graph = value()?;
+                    cli.options.graph = Some(GraphFormat::from_name(&graph).ok_or_else(|| {
+                        let names: Vec<_> = GraphFormat::ALL.iter().map(|f| f.name()).collect();
+                        format!(
+                            "unknown graph format `{graph}`; expected one of {}",
+                            names.join(", ")
+                        )
+                    })?);
+                }
+                "--graph-entry" => cli.options.graph_entry = Some(value()?),
+                "--root-causes" => cli.options.root_causes = true,
+                "--no-summarize-deps" => cli.summarize_deps = false,
+                // Target selection is done by cargo.
+                "--tests" | "--examples" | "--benches" | "--all-targets" => {
+                    cli.cargo_args.push(arg.clone())
+                }
+                "--target" => {
+                    let target = value()?;
+                    cli.cargo_args.extend(["--target".to_owned(), target]);
+                }
+                _ if name.starts_with('-') => {
+                    return Err(format!(
+                        "unexpected option `{arg}`; cargo options go after `--`, \
+                         like `cargo redpen -- {arg}`"
+                    ));
+                }
+                "check" | "report" | "explain" | "clean" | "diff" if !has_command => {
+                    has_command = true;
+                    cli.command = match name {
+                        "check" => Command::Check,
+                        "report" => Command::Report,
+                        "explain" => Command::Explain,
+                        "clean" => Command::Clean,
+                        _ => Command::Diff,
+                    };
+                }
+                _ if cli.command == Command::Explain && cli.explain.is_none() => {
+                    cli.explain = Some(Check::from_name(arg).ok_or_else(|| unknown_check(arg))?);
+                }
+                _ if cli.command == Command::Diff && cli.diff.len() < 2 => {
+                    cli.diff.push(arg.into())
+                }
+                _ => return Err(format!("unexpected argument `{arg}`")),
+            }
+        }
+        if cli.command == Command::Diff && cli.diff.len() < 2 {
+            return Err("`diff` needs two reports, like `diff old.json new.json`".to_owned());
+        }
+        Ok(Parsed::Run(Box::new(cli)))
+    }
+}
+
+fn parse_checks(value: &str) -> Result<Vec<Check>, String> {
+    value
+        .split(',')
+        .map(|check| Check::from_name(check.trim()).ok_or_else(|| unknown_check(check)))
+        .collect()
+}
+
+fn unknown_check(check: &str) -> String {
+    let names: Vec<_> = Check::ALL.iter().map(|check| check.name()).collect();
+    format!(
+        "unknown check `{check}`; expected one of {}",
+        names.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Parsed, String> {
+        let args: Vec<_> = args.split_whitespace().map(str::to_owned).collect();
+        Cli::parse(&args)
+    }
+
+    fn run(args: &str) -> Cli {
+        match parse(args) {
+            Ok(Parsed::Run(cli)) => *cli,
+            other => panic!("`{args}` is parsed as {other:?}"),
+        }
+    }
+
+    fn error(args: &str) -> String {
+        parse(args).expect_err(args)
+    }
+
+    #[test]
+    fn cargo_args_follow_double_dash() {
+        let cli = run("--checks panic -- -p utils --checks locks -- x");
+        assert_eq!(cli.options.checks, [Check::Panic]);
+        assert_eq!(
+            cli.cargo_args,
+            ["-p", "utils", "--checks", "locks", "--", "x"]
+        );
+
+        let cli = run("--tests --target thumbv7em-none-eabihf -- -p utils");
+        assert_eq!(
+            cli.cargo_args,
+            [
+                "-p",
+                "utils",
+                "--tests",
+                "--target",
+                "thumbv7em-none-eabihf"
+            ]
+        );
+    }
+
+    #[test]
+    fn values_inline_or_separate() {
+        for args in ["--format=json --entries=pub", "--format json --entries pub"] {
+            let cli = run(args);
+            assert_eq!(cli.options.format, Format::Json, "{args}");
+            assert_eq!(cli.options.entries, Entries::Pub, "{args}");
+        }
+        let cli = run("--graph-entry=parse::<u8> --write-baseline base=line.json");
+        assert_eq!(cli.options.graph_entry.as_deref(), Some("parse::<u8>"));
+        assert_eq!(cli.write_baseline, Some(PathBuf::from("base=line.json")));
+    }
+
+    #[test]
+    fn command_before_or_after_options() {
+        for args in ["report --skip-tests", "--skip-tests report"] {
+            let cli = run(args);
+            assert_eq!(cli.command, Command::Report, "{args}");
+            assert!(cli.options.skip_tests, "{args}");
+        }
+        assert_eq!(run("").command, Command::Check);
+        assert!(run("").summarize_deps);
+        assert!(!run("check --no-summarize-deps").summarize_deps);
+        assert_eq!(run("--root-causes clean").command, Command::Clean);
+        assert!(matches!(parse("report --help"), Ok(Parsed::Help)));
+        assert!(matches!(parse("-V"), Ok(Parsed::Version)));
+    }
+
+    #[test]
+    fn explain_and_diff_arguments() {
+        assert_eq!(run("explain").explain, None);
+        assert_eq!(run("explain locks").explain, Some(Check::Locks));
+        assert_eq!(run("--format json explain isr").explain, Some(Check::Isr));
+
+        let cli = run("diff old.json --format json new.json");
+        assert_eq!(cli.command, Command::Diff);
+        assert_eq!(
+            cli.diff,
+            [PathBuf::from("old.json"), PathBuf::from("new.json")]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("--config"), "`--config` requires a value");
+        assert_eq!(error("--graph-entry"), "`--graph-entry` requires a value");
+        assert_eq!(
+            error("--format xml"),
+            "unknown format `xml`; expected `human`, `json` or `os-checker`"
+        );
+        assert_eq!(
+            error("--entries private"),
+            "unknown entries `private`; expected one of all, pub, exports, tagged"
+        );
+        assert_eq!(
+            error("--graph svg"),
+            "unknown graph format `svg`; expected one of dot, json, graphml"
+        );
+        let unknown_check = "unknown check `deadlock`; expected one of panic, forbid, \
+                             recursion, locks, isr, stack-depth, panics-doc";
+        assert_eq!(error("--checks panic,deadlock"), unknown_check);
+        assert_eq!(error("explain deadlock"), unknown_check);
+        assert_eq!(
+            error("-p utils"),
+            "unexpected option `-p`; cargo options go after `--`, like `cargo redpen -- -p`"
+        );
+        assert_eq!(error("check report"), "unexpected argument `report`");
+        assert_eq!(error("explain locks isr"), "unexpected argument `isr`");
+        assert_eq!(
+            error("diff a.json b.json c.json"),
+            "unexpected argument `c.json`"
+        );
+        assert_eq!(
+            error("diff a.json"),
+            "`diff` needs two reports, like `diff old.json new.json`"
+        );
+    }
+}
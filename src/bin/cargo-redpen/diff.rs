@@ -0,0 +1,196 @@
+//! Compare two reports, like `target/redpen/findings.json` from two revisions.
+
+use redpen::report::{CrateReport, Finding, Location};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
+
+/// Findings added, removed, and moved to another location between two reports.
+pub struct Diff {
+    pub added: Vec<Finding>,
+    pub removed: Vec<Finding>,
+    /// Findings in both reports, at the old and the new location.
+    pub moved: Vec<(Finding, Finding)>,
+}
+
+impl Diff {
+    /// Match findings by [`Finding::fingerprint`]. Findings with the same fingerprint are
+    /// matched in order.
+    pub fn new(old: Vec<Finding>, new: Vec<Finding>) -> Self {
+        let mut by_fingerprint: HashMap<&str, VecDeque<usize>> = HashMap::new();
+        for (idx, finding) in old.iter().enumerate() {
+            by_fingerprint
+                .entry(&finding.fingerprint)
+                .or_default()
+                .push_back(idx);
+        }
+        let mut matched = vec![false; old.len()];
+
+        let mut added = Vec::new();
+        let mut moved = Vec::new();
+        for finding in new {
+            match by_fingerprint
+                .get_mut(&*finding.fingerprint)
+                .and_then(VecDeque::pop_front)
+            {
+                Some(idx) => {
+                    matched[idx] = true;
+                    if old[idx].location() != finding.location() {
+                        moved.push((old[idx].clone(), finding));
+                    }
+                }
+                None => added.push(finding),
+            }
+        }
+        let removed = old
+            .into_iter()
+            .zip(matched)
+            .filter_map(|(finding, matched)| (!matched).then_some(finding))
+            .collect();
+        Diff {
+            added,
+            removed,
+            moved,
+        }
+    }
+
+    /// Whether the new report has findings unknown to the old one, which fails the diff.
+    /// Removed and moved findings don't.
+    pub fn has_additions(&self) -> bool {
+        !self.added.is_empty()
+    }
+
+    pub fn print(&self) {
+        println!("Added findings: {}", self.added.len());
+        for finding in &self.added {
+            println!("  + {} {}", location(finding.location()), describe(finding));
+        }
+        println!("Removed findings: {}", self.removed.len());
+        for finding in &self.removed {
+            println!("  - {} {}", location(finding.location()), describe(finding));
+        }
+        println!("Moved findings: {}", self.moved.len());
+        for (old, new) in &self.moved {
+            println!(
+                "  ~ {} -> {} {}",
+                location(old.location()),
+                location(new.location()),
+                describe(new)
+            );
+        }
+    }
+}
+
+/// Load findings from merged results of `cargo redpen`, or from JSON lines printed with
+/// `--format json`.
+pub fn load(path: &Path) -> Result<Vec<Finding>, String> {
+    let json = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    if let Ok(reports) = serde_json::from_str::<Vec<CrateReport>>(&json) {
+        return Ok(reports.into_iter().flat_map(|r| r.findings).collect());
+    }
+    json.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|err| err.to_string()))
+        .collect()
+}
+
+fn location(loc: Option<&Location>) -> String {
+    match loc {
+        Some(loc) => format!("{}:{}:{}", loc.file, loc.line, loc.column),
+        None => "<unknown>".to_owned(),
+    }
+}
+
+fn describe(finding: &Finding) -> String {
+    format!(
+        "[{}] `{}`: {}",
+        finding.check.name(),
+        finding.function,
+        finding.title
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redpen::{options::Check, report::Level};
+
+    fn finding(function: &str, fingerprint: &str, line: usize) -> Finding {
+        let mut finding = Finding::new(
+            Check::Panic,
+            Level::Error,
+            "A possible panic spot is found.",
+            function.to_owned(),
+        )
+        .origin(Location {
+            file: "src/lib.rs".to_owned(),
+            line,
+            column: 5,
+        });
+        finding.fingerprint = fingerprint.to_owned();
+        finding
+    }
+
+    fn functions(findings: &[Finding]) -> Vec<&str> {
+        findings.iter().map(|f| &*f.function).collect()
+    }
+
+    #[test]
+    fn added_removed_and_moved() {
+        let old = vec![
+            finding("a", "1", 10),
+            finding("b", "2", 20),
+            finding("c", "3", 30),
+        ];
+        let new = vec![
+            finding("a", "1", 10),
+            finding("c", "3", 33),
+            finding("d", "4", 40),
+        ];
+        let diff = Diff::new(old, new);
+        assert_eq!(functions(&diff.added), ["d"]);
+        assert_eq!(functions(&diff.removed), ["b"]);
+        let moved: Vec<_> = diff
+            .moved
+            .iter()
+            .map(|(old, new)| (old.location().unwrap().line, new.location().unwrap().line))
+            .collect();
+        assert_eq!(moved, [(30, 33)]);
+        assert!(diff.has_additions());
+    }
+
+    #[test]
+    fn same_fingerprints_match_in_order() {
+        let old = vec![finding("f", "1", 10), finding("f", "1", 20)];
+        let new = vec![
+            finding("f", "1", 12),
+            finding("f", "1", 20),
+            finding("f", "1", 30),
+        ];
+        let diff = Diff::new(old, new);
+        // The first old finding moves to the first new one, and the third new one is added.
+        let moved: Vec<_> = diff
+            .moved
+            .iter()
+            .map(|(old, new)| (old.location().unwrap().line, new.location().unwrap().line))
+            .collect();
+        assert_eq!(moved, [(10, 12)]);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].location().unwrap().line, 30);
+        assert!(diff.removed.is_empty());
+    }
+
+    #[test]
+    fn only_additions_fail() {
+        let old = vec![finding("a", "1", 10), finding("b", "2", 20)];
+        let new = vec![finding("a", "1", 11)];
+        let diff = Diff::new(old, new);
+        assert_eq!(functions(&diff.removed), ["b"]);
+        assert_eq!(diff.moved.len(), 1);
+        assert!(!diff.has_additions());
+
+        let diff = Diff::new(Vec::new(), Vec::new());
+        assert!(!diff.has_additions());
+    }
+}
@@ -0,0 +1,444 @@
+//! A static HTML report of findings, with no external assets, for reading in a browser.
+
+use redpen::{
+    options::Check,
+    report::{CrateReport, Finding, Level, PanicKind, Snippet},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::Path,
+};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; }
+th, td { padding: 0.2em 0.8em; border-bottom: 1px solid #ddd; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+fieldset { display: inline-block; margin: 1em 1em 1em 0; border: 1px solid #ccc; }
+label { margin-right: 1em; white-space: nowrap; }
+details { margin: 0.3em 0 0.3em 1.2em; }
+summary { cursor: pointer; }
+summary code { font-weight: bold; }
+.count { color: #777; }
+.finding { margin: 0.8em 0 0.8em 1.2em; padding: 0.5em 1em; border-left: 4px solid #999; background: #fafafa; }
+.finding.error { border-color: #c00; }
+.finding.warning { border-color: #d80; }
+.level { font-weight: bold; }
+.error .level { color: #c00; }
+.warning .level { color: #d80; }
+.check { color: #555; }
+.meta, .fingerprint { color: #555; font-size: 0.9em; }
+pre { background: #fff; border: 1px solid #ddd; padding: 0.5em; overflow-x: auto; }
+.line { color: #999; user-select: none; }
+mark.primary { background: #fcc; }
+mark.context { background: #def; }
+ul.labels, ul.notes { margin: 0.3em 0; }
+li.primary { color: #c00; }
+details.witness { margin-left: 0; }
+";
+
+const SCRIPT: &str = "
+const boxes = document.querySelectorAll('.filters input');
+function update() {
+  const on = new Set([...boxes].filter(b => b.checked).map(b => b.value));
+  for (const f of document.querySelectorAll('.finding')) {
+    const kinds = f.dataset.kinds ? f.dataset.kinds.split(' ') : [];
+    f.hidden = !on.has('check:' + f.dataset.check)
+      || (kinds.length > 0 && !kinds.some(k => on.has('kind:' + k)));
+  }
+  for (const d of document.querySelectorAll('details.tree')) {
+    d.hidden = !d.querySelector('.finding:not([hidden])');
+  }
+}
+for (const b of boxes) b.addEventListener('change', update);
+";
+
+/// Findings of a module, and its submodules by name.
+#[derive(Default)]
+struct Module<'r> {
+    findings: Vec<&'r Finding>,
+    children: BTreeMap<String, Module<'r>>,
+}
+
+/// Write the report of all crates to one HTML file.
+pub fn write(path: &Path, reports: &[CrateReport]) {
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(path, render(reports)));
+    if let Err(err) = written {
+        eprintln!("error: failed to write `{}`: {err}", path.display());
+        std::process::exit(1);
+    }
+}
+
+fn render(reports: &[CrateReport]) -> String {
+    let findings = || reports.iter().flat_map(|r| &r.findings);
+    let checks: Vec<Check> = Check::ALL
+        .into_iter()
+        .filter(|&c| findings().any(|f| f.check == c))
+        .collect();
+    let kinds: BTreeSet<PanicKind> = findings().flat_map(|f| f.panic_kinds.clone()).collect();
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>redpen report</title>\n",
+    );
+    _ = writeln!(
+        html,
+        "<style>{STYLE}</style>\n</head>\n<body>\n<h1>redpen report</h1>"
+    );
+
+    // Counts of findings by crate and check.
+    html.push_str("<table>\n<tr><th>Crate</th><th>Entries</th><th>Functions</th>");
+    for check in &checks {
+        _ = write!(html, "<th>{}</th>", check.name());
+    }
+    html.push_str("</tr>\n");
+    for report in reports {
+        let functions: BTreeSet<_> = report.findings.iter().map(|f| &f.function).collect();
+        _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td>",
+            escape(&crate_name(report)),
+            report.entries,
+            functions.len()
+        );
+        for &check in &checks {
+            let count = report.findings.iter().filter(|f| f.check == check).count();
+            _ = write!(html, "<td>{count}</td>");
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+
+    if findings().next().is_none() {
+        html.push_str("<p>No findings.</p>\n</body>\n</html>\n");
+        return html;
+    }
+
+    html.push_str("<div class=\"filters\">\n<fieldset><legend>Checks</legend>\n");
+    for check in &checks {
+        let name = check.name();
+        _ = writeln!(
+            html,
+            "<label><input type=\"checkbox\" value=\"check:{name}\" checked> {name}</label>"
+        );
+    }
+    html.push_str("</fieldset>\n");
+    if !kinds.is_empty() {
+        html.push_str("<fieldset><legend>Panic kinds</legend>\n");
+        for kind in kinds.iter().map(|kind| kind.name()) {
+            _ = writeln!(
+                html,
+                "<label><input type=\"checkbox\" value=\"kind:{kind}\" checked> {kind}</label>"
+            );
+        }
+        html.push_str("</fieldset>\n");
+    }
+    html.push_str("</div>\n");
+
+    for report in reports.iter().filter(|r| !r.findings.is_empty()) {
+        let mut root = Module::default();
+        for finding in &report.findings {
+            let mut segments = path_segments(&finding.function);
+            segments.pop();
+            if segments.first() == Some(&&*report.krate) {
+                segments.remove(0);
+            }
+            let module = segments.into_iter().fold(&mut root, |module, segment| {
+                module.children.entry(segment.to_owned()).or_default()
+            });
+            module.findings.push(finding);
+        }
+        render_module(&mut html, &crate_name(report), &root);
+    }
+
+    _ = writeln!(html, "<script>{SCRIPT}</script>\n</body>\n</html>");
+    html
+}
+
+fn crate_name(report: &CrateReport) -> String {
+    if report.test {
+        format!("{} (test)", report.krate)
+    } else {
+        report.krate.clone()
+    }
+}
+
+fn render_module(html: &mut String, name: &str, module: &Module) {
+    _ = writeln!(
+        html,
+        "<details class=\"tree\" open><summary><code>{}</code> <span class=\"count\">({})</span></summary>",
+        escape(name),
+        module.len()
+    );
+    for finding in &module.findings {
+        render_finding(html, finding);
+    }
+    for (name, child) in &module.children {
+        render_module(html, name, child);
+    }
+    html.push_str("</details>\n");
+}
+
+impl Module<'_> {
+    /// Number of findings in the module and its submodules.
+    fn len(&self) -> usize {
+        self.findings.len() + self.children.values().map(Module::len).sum::<usize>()
+    }
+}
+
+fn render_finding(html: &mut String, finding: &Finding) {
+    let level = level_name(finding.level);
+    let kinds: BTreeSet<&str> = finding.panic_kinds.iter().map(|k| k.name()).collect();
+    _ = writeln!(
+        html,
+        "<div class=\"finding {level}\" data-check=\"{}\" data-kinds=\"{}\">",
+        finding.check.name(),
+        kinds.into_iter().collect::<Vec<_>>().join(" ")
+    );
+    _ = writeln!(
+        html,
+        "<div><span class=\"level\">{level}</span> <span class=\"check\">[{}]</span> {}</div>",
+        finding.check.name(),
+        escape(&finding.title)
+    );
+    _ = write!(
+        html,
+        "<div class=\"meta\">In <code>{}</code>",
+        escape(&finding.function)
+    );
+    if let Some(loc) = finding.location() {
+        _ = write!(
+            html,
+            " at {}:{}:{}",
+            escape(&loc.file),
+            loc.line,
+            loc.column
+        );
+    }
+    html.push_str("</div>\n");
+
+    for snippet in &finding.snippets {
+        render_snippet(html, snippet);
+    }
+    if !finding.notes.is_empty() {
+        html.push_str("<ul class=\"notes\">\n");
+        for note in &finding.notes {
+            _ = writeln!(
+                html,
+                "<li><b>{}:</b> {}</li>",
+                level_name(note.level),
+                escape(&note.message)
+            );
+        }
+        html.push_str("</ul>\n");
+    }
+    for (idx, witness) in finding.witnesses.iter().enumerate() {
+        let Some(last) = witness.last() else { continue };
+        _ = write!(
+            html,
+            "<details class=\"witness\"><summary>Call chain to <code>{}</code>",
+            escape(last)
+        );
+        if let Some(kind) = finding.panic_kinds.get(idx) {
+            _ = write!(html, " ({})", kind.name());
+        }
+        html.push_str("</summary>\n<ol>\n");
+        for name in witness {
+            _ = writeln!(html, "<li><code>{}</code></li>", escape(name));
+        }
+        html.push_str("</ol>\n</details>\n");
+    }
+    if !finding.fingerprint.is_empty() {
+        _ = writeln!(
+            html,
+            "<div class=\"fingerprint\">Fingerprint: {}</div>",
+            finding.fingerprint
+        );
+    }
+    html.push_str("</div>\n");
+}
+
+/// Source of a snippet with line numbers and annotated spans highlighted, followed by the
+/// labels of annotations.
+fn render_snippet(html: &mut String, snippet: &Snippet) {
+    let src = &snippet.source;
+    let mut cuts = BTreeSet::from([0, src.len()]);
+    for annot in &snippet.annotations {
+        cuts.insert(annot.range.start.min(src.len()));
+        cuts.insert(annot.range.end.min(src.len()));
+    }
+    for (idx, _) in src.match_indices('\n') {
+        cuts.insert(idx);
+        cuts.insert(idx + 1);
+    }
+
+    let last_line = snippet.line_start + src.matches('\n').count();
+    let width = last_line.to_string().len();
+    let mut line = snippet.line_start;
+    _ = write!(
+        html,
+        "<pre><span class=\"line\">{line:>width$}</span> {}",
+        " ".repeat(snippet.column_start.saturating_sub(1))
+    );
+    let cuts: Vec<usize> = cuts.into_iter().collect();
+    for range in cuts.windows(2) {
+        let (start, end) = (range[0], range[1]);
+        let Some(text) = src.get(start..end) else {
+            continue;
+        };
+        if text == "\n" {
+            line += 1;
+            _ = write!(html, "\n<span class=\"line\">{line:>width$}</span> ");
+            continue;
+        }
+        let covering: Vec<_> = snippet
+            .annotations
+            .iter()
+            .filter(|a| a.range.start <= start && end <= a.range.end)
+            .collect();
+        if covering.is_empty() {
+            html.push_str(&escape(text));
+            continue;
+        }
+        let class = if covering.iter().any(|a| a.primary) {
+            "primary"
+        } else {
+            "context"
+        };
+        let labels: Vec<&str> = covering.iter().map(|a| &*a.label).collect();
+        _ = write!(
+            html,
+            "<mark class=\"{class}\" title=\"{}\">{}</mark>",
+            escape(&labels.join("\n")),
+            escape(text)
+        );
+    }
+    html.push_str("</pre>\n");
+
+    if snippet.annotations.is_empty() {
+        return;
+    }
+    let mut annots: Vec<_> = snippet.annotations.iter().collect();
+    annots.sort_by_key(|a| (a.location.line, a.location.column));
+    html.push_str("<ul class=\"labels\">\n");
+    for annot in annots {
+        let class = if annot.primary { "primary" } else { "context" };
+        _ = writeln!(
+            html,
+            "<li class=\"{class}\">{}:{}: {}</li>",
+            annot.location.line,
+            annot.location.column,
+            escape(&annot.label)
+        );
+    }
+    html.push_str("</ul>\n");
+}
+
+/// Segments of a path, not splitting inside `<...>` like `<S as T>::f`.
+fn path_segments(path: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    let bytes = path.as_bytes();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'<' => depth += 1,
+            b'>' => depth = depth.saturating_sub(1),
+            b':' if depth == 0 && bytes.get(idx + 1) == Some(&b':') => {
+                segments.push(&path[start..idx]);
+                start = idx + 2;
+                idx += 1;
+            }
+            _ => (),
+        }
+        idx += 1;
+    }
+    segments.push(&path[start..]);
+    segments
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warning => "warning",
+        Level::Note => "note",
+        Level::Help => "help",
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(function: &str) -> Finding {
+        Finding::new(
+            Check::Panic,
+            Level::Error,
+            "A possible panic spot is found.",
+            function.to_owned(),
+        )
+        .witnesses([
+            vec![
+                function.to_owned(),
+                "core::option::unwrap_failed".to_owned(),
+            ],
+            vec![function.to_owned(), "std::rt::panic_fmt".to_owned()],
+        ])
+        .panic_kinds([PanicKind::Unwrap, PanicKind::Explicit])
+    }
+
+    #[test]
+    fn path_segments_keep_qualified_self() {
+        assert_eq!(path_segments("app"), ["app"]);
+        assert_eq!(path_segments("app::net::send"), ["app", "net", "send"]);
+        assert_eq!(
+            path_segments("<app::S as std::ops::Drop>::drop"),
+            ["<app::S as std::ops::Drop>", "drop"]
+        );
+        assert_eq!(
+            path_segments("app::Vec::<T, A>::push"),
+            ["app", "Vec", "<T, A>", "push"]
+        );
+    }
+
+    #[test]
+    fn witnesses_show_panic_kinds() {
+        let mut html = String::new();
+        render_finding(&mut html, &finding("app::main"));
+        assert!(html.contains(r#"data-kinds="explicit unwrap""#), "{html}");
+        assert!(
+            html.contains("Call chain to <code>core::option::unwrap_failed</code> (unwrap)"),
+            "{html}"
+        );
+        assert!(
+            html.contains("Call chain to <code>std::rt::panic_fmt</code> (explicit)"),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn panic_kinds_filter_lists_found_kinds() {
+        let report = CrateReport {
+            krate: "app".to_owned(),
+            test: false,
+            entries: 1,
+            findings: vec![finding("app::main")],
+            graph: None,
+        };
+        let html = render(&[report]);
+        assert!(html.contains(r#"value="kind:unwrap""#), "{html}");
+        assert!(html.contains(r#"value="kind:explicit""#), "{html}");
+        assert!(!html.contains(r#"value="kind:bounds""#), "{html}");
+    }
+}
@@ -0,0 +1,567 @@
+mod aggregate;
+mod cli;
+mod diff;
+mod html;
+
+use aggregate::Artifact;
+use cli::{Cli, Command as CliCommand, HELP, Parsed};
+use redpen::{
+    baseline::Baseline,
+    options::{Check, ENV_OPTIONS, GraphFormat},
+};
+use std::{
+    collections::HashSet,
+    env::var,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Command, Stdio, exit},
+    time::SystemTime,
+};
+
+const RUSTC_WRAPPER: &str = "redpen";
+const CARGO_TOOL: &str = "cargo-redpen";
+
+const ENV_RUSTC_WRAPPER: &str = "REDPEN";
+const ENV_CARGO_TOOL: &str = "CARGO_REDPEN";
+/// A `RUSTC_WRAPPER` of the user, like sccache, which runs crates compiled by rustc.
+const ENV_CHAINED_WRAPPER: &str = "CARGO_REDPEN_RUSTC_WRAPPER";
+/// Set to `1` unless `--no-summarize-deps`, for the wrapper to summarize dependencies.
+const ENV_SUMMARIZE_DEPS: &str = "CARGO_REDPEN_SUMMARIZE_DEPS";
+
+fn main() {
+    // Search CLI through environment variables, or just use the name if absent.
+    let cargo_tool = &*var(ENV_CARGO_TOOL).unwrap_or_else(|_| CARGO_TOOL.to_owned());
+    let redpen = &*var(ENV_RUSTC_WRAPPER).unwrap_or_else(|_| RUSTC_WRAPPER.to_owned());
+
+    let args = std::env::args().collect::<Vec<_>>();
+
+    if std::env::var("WRAPPER").as_deref() == Ok("1") {
+        // cargo invokes `$RUSTC_WRAPPER $RUSTC args...` for every crate.
+        let (rustc, rustc_args) = (&*args[1], &encode_mir(&args[2..]));
+        match target(rustc_args, |name| var(name).ok()) {
+            Target::Analyzed => run(redpen, rustc_args, &[]),
+            Target::Summarized => {
+                let mut args = rustc_args.to_vec();
+                args.push("--redpen-summary-only".to_owned());
+                run(redpen, &args, &[]);
+            }
+            Target::Compiled => match var(ENV_CHAINED_WRAPPER) {
+                Ok(wrapper) if !wrapper.is_empty() => {
+                    let mut args = vec![rustc.to_owned()];
+                    args.extend(rustc_args.iter().cloned());
+                    run(&wrapper, &args, &[]);
+                }
+                _ => run(rustc, rustc_args, &[]),
+            },
+        }
+        return;
+    }
+
+    // Entry for cargo-redpen: invoked as `cargo-redpen redpen args...` by `cargo redpen`,
+    // or directly as `cargo-redpen args...`.
+    let mut args = &args[1..];
+    if args.first().is_some_and(|arg| arg == RUSTC_WRAPPER) {
+        args = &args[1..];
+    }
+    let mut cli = match Cli::parse(args) {
+        Ok(Parsed::Run(cli)) => *cli,
+        Ok(Parsed::Help) => return print!("{HELP}"),
+        Ok(Parsed::Version) => return println!("cargo-redpen {}", env!("CARGO_PKG_VERSION")),
+        Err(err) => {
+            eprintln!("error: {err}\n\nFor more information, try `cargo redpen --help`.");
+            exit(1);
+        }
+    };
+
+    match cli.command {
+        CliCommand::Check | CliCommand::Report => check(&mut cli, cargo_tool),
+        CliCommand::Explain => explain(cli.explain),
+        CliCommand::Diff => diff(&cli.diff[0], &cli.diff[1]),
+        CliCommand::Clean => {
+            let dir = redpen_dir(&cli.cargo_args);
+            if dir.exists()
+                && let Err(err) = std::fs::remove_dir_all(&dir)
+            {
+                eprintln!("error: failed to remove `{}`: {err}", dir.display());
+                exit(1);
+            }
+        }
+    }
+}
+
+/// Run `cargo check` with cargo-redpen as the rustc wrapper.
+fn check(cli: &mut Cli, cargo_tool: &str) {
+    let opts = &mut cli.options;
+    if let Some(config) = &opts.config {
+        // The driver runs in each package's directory.
+        opts.config = Some(std::path::absolute(config).unwrap_or_else(|_| config.clone()));
+    }
+    let baseline = opts.baseline.as_mut().map(|path| {
+        *path = std::path::absolute(&*path).unwrap_or_else(|_| path.clone());
+        Baseline::load(path).unwrap_or_else(|err| {
+            eprintln!("error: failed to load baseline `{}`: {err}", path.display());
+            exit(1);
+        })
+    });
+    let redpen_dir = redpen_dir(&cli.cargo_args);
+    if cli.command == CliCommand::Report {
+        opts.report_dir = Some(redpen_dir.join("report"));
+    }
+    opts.summary_dir = Some(redpen_dir.join("summaries"));
+    opts.cache_dir = Some(redpen_dir.join("cache"));
+    if opts.graph_entry.is_some() && opts.graph.is_none() {
+        opts.graph = Some(GraphFormat::Dot);
+    }
+    if opts.graph.is_some() {
+        opts.graph_dir = Some(redpen_dir.join("graph"));
+    }
+    let results_dir = redpen_dir.join("results");
+    opts.results_dir = Some(results_dir.clone());
+    opts.epoch = epoch(&redpen_dir);
+    let opts = serde_json::to_string(&cli.options).unwrap();
+
+    // Cargo reports every checked crate, fresh ones included, in JSON messages, which
+    // tell whose results to aggregate. Diagnostics are still rendered by cargo.
+    let aggregate = !cli
+        .cargo_args
+        .iter()
+        .any(|arg| arg.starts_with("--message-format"));
+    if !aggregate && cli.write_baseline.is_some() {
+        eprintln!("error: `--write-baseline` can't be used with `--message-format`");
+        exit(1);
+    }
+    // A target directory of its own keeps crates fresh from a plain `cargo check` from
+    // skipping the driver, and redpen's artifacts from invalidating those of cargo.
+    let (_, mut cargo_args) = split_target_dir(&cli.cargo_args);
+    cargo_args.push("--target-dir".to_owned());
+    cargo_args.push(redpen_dir.join("build").to_string_lossy().into_owned());
+    let mut args = vec!["check".to_owned()];
+    if aggregate {
+        args.push("--message-format=json-render-diagnostics".to_owned());
+    }
+    args.extend(cargo_args.iter().cloned());
+    // Cargo runs a single wrapper, so chain the one of the user if any.
+    let chained = var("RUSTC_WRAPPER")
+        .ok()
+        .filter(|wrapper| Path::new(wrapper).file_stem() != Some(CARGO_TOOL.as_ref()))
+        .unwrap_or_default();
+    let vars = [
+        ("RUSTC_WRAPPER", cargo_tool),
+        (ENV_CHAINED_WRAPPER, &chained),
+        (
+            ENV_SUMMARIZE_DEPS,
+            if cli.summarize_deps { "1" } else { "" },
+        ),
+        (ENV_OPTIONS, &opts),
+        ("WRAPPER", "1"),
+    ];
+    if !aggregate {
+        return run("cargo", &args, &vars);
+    }
+    let artifacts = run_with_artifacts("cargo", &args, &vars);
+
+    let primary = primary_packages(&cargo_args);
+    let mut reports = aggregate::load(&results_dir, &artifacts, &primary).unwrap_or_else(|stale| {
+        // A package selected with `-p` after it was only summarized as a dependency is
+        // fresh to cargo, as whether redpen analyzes a crate isn't in its fingerprint.
+        // Removing its artifacts has cargo check it again on the next run.
+        for artifact in &stale {
+            eprintln!("error: redpen didn't analyze `{}`", artifact.display());
+            let _ = std::fs::remove_file(artifact);
+        }
+        eprintln!("note: these crates were only summarized as dependencies before");
+        eprintln!("help: run `cargo redpen` again to analyze them");
+        exit(1);
+    });
+    let graphs = reports.iter().any(|report| report.graph.is_some());
+    if let Some(path) = &cli.write_baseline {
+        let findings = reports.iter().flat_map(|r| &r.findings);
+        let baseline = Baseline::new(findings);
+        if let Err(err) = baseline.write(path) {
+            eprintln!(
+                "error: failed to write baseline `{}`: {err}",
+                path.display()
+            );
+            exit(1);
+        }
+        eprintln!(
+            "Recorded {} findings to the baseline `{}`.",
+            baseline.len(),
+            path.display()
+        );
+    }
+    let known = baseline.map_or(0, |baseline| {
+        aggregate::remove_known(&mut reports, &baseline)
+    });
+    if reports.is_empty() {
+        return;
+    }
+    let merged = redpen_dir.join("findings.json");
+    aggregate::write_merged(&merged, &reports);
+    eprintln!();
+    aggregate::print_table(&reports, &cli.options.checks);
+    if known > 0 {
+        eprintln!("\n{known} findings known in the baseline are not reported.");
+    }
+    eprintln!(
+        "\nFindings of all crates are merged into `{}`.",
+        merged.display()
+    );
+    if let Some(dir) = &cli.options.report_dir {
+        let html = dir.join("index.html");
+        html::write(&html, &reports);
+        eprintln!("Findings are written to `{}`.", dir.display());
+        eprintln!("An HTML report is written to `{}`.", html.display());
+    }
+    if let Some(dir) = &cli.options.graph_dir {
+        if graphs {
+            eprintln!("Call graphs are written to `{}`.", dir.display());
+        } else if let Some(entry) = &cli.options.graph_entry {
+            eprintln!("warning: no crate has a fn `{entry}` to write the call graph from");
+        }
+    }
+}
+
+/// Print the difference between two reports, and fail if findings are added.
+fn diff(old: &Path, new: &Path) {
+    let load = |path: &Path| {
+        diff::load(path).unwrap_or_else(|err| {
+            eprintln!("error: failed to load report `{}`: {err}", path.display());
+            exit(2);
+        })
+    };
+    let diff = diff::Diff::new(load(old), load(new));
+    diff.print();
+    if diff.has_additions() {
+        exit(1);
+    }
+}
+
+fn explain(check: Option<Check>) {
+    match check {
+        Some(check) => println!("{}: {}", check.name(), check.description()),
+        None => {
+            for check in Check::ALL {
+                println!("{}:\n    {}\n", check.name(), check.description());
+            }
+        }
+    }
+}
+
+/// `target/redpen` of the workspace, or of the package selected by `--manifest-path`, or
+/// `redpen` in the directory given by `--target-dir`.
+fn redpen_dir(cargo_args: &[String]) -> PathBuf {
+    #[derive(serde::Deserialize)]
+    struct Metadata {
+        target_directory: PathBuf,
+    }
+
+    if let (Some(dir), _) = split_target_dir(cargo_args) {
+        return std::path::absolute(&dir).unwrap_or(dir).join("redpen");
+    }
+    let mut cmd = Command::new("cargo");
+    cmd.args(["metadata", "--format-version=1", "--no-deps"]);
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--manifest-path" {
+            cmd.arg(arg).args(args.next());
+        } else if arg.starts_with("--manifest-path=") {
+            cmd.arg(arg);
+        }
+    }
+    let output = cmd.stderr(Stdio::inherit()).output();
+    let metadata = match output {
+        Ok(output) if output.status.success() => {
+            serde_json::from_slice::<Metadata>(&output.stdout).ok()
+        }
+        _ => None,
+    };
+    match metadata {
+        Some(metadata) => metadata.target_directory.join("redpen"),
+        None => {
+            eprintln!("error: failed to locate the target directory with `cargo metadata`");
+            exit(1);
+        }
+    }
+}
+
+/// The `--target-dir` in cargo arguments, if any, and the other arguments.
+fn split_target_dir(cargo_args: &[String]) -> (Option<PathBuf>, Vec<String>) {
+    let mut target_dir = None;
+    let mut rest = Vec::new();
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--target-dir" {
+            target_dir = args.next().map(PathBuf::from);
+        } else if let Some(dir) = arg.strip_prefix("--target-dir=") {
+            target_dir = Some(PathBuf::from(dir));
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+    (target_dir, rest)
+}
+
+/// Package ids of the packages selected by cargo arguments, the roots of cargo's unit
+/// graph, whose crates cargo marks with `CARGO_PRIMARY_PACKAGE` for the wrapper to analyze.
+fn primary_packages(cargo_args: &[String]) -> HashSet<String> {
+    #[derive(serde::Deserialize)]
+    struct UnitGraph {
+        units: Vec<Unit>,
+        roots: Vec<usize>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Unit {
+        pkg_id: String,
+    }
+
+    let output = Command::new("cargo")
+        .args(["check", "--unit-graph", "-Zunstable-options"])
+        .args(cargo_args)
+        .stderr(Stdio::inherit())
+        .output();
+    let graph = match output {
+        Ok(output) if output.status.success() => {
+            serde_json::from_slice::<UnitGraph>(&output.stdout).ok()
+        }
+        _ => None,
+    };
+    let Some(graph) = graph else {
+        eprintln!("error: failed to list the selected packages with `cargo check --unit-graph`");
+        exit(1);
+    };
+    let roots = graph.roots.iter().filter_map(|&root| graph.units.get(root));
+    roots.map(|unit| unit.pkg_id.clone()).collect()
+}
+
+/// Time `target/redpen` was created, recorded in `target/redpen/epoch`. It's passed to
+/// the driver, so cargo reruns fresh crates to refill the directory after it's cleaned.
+fn epoch(redpen_dir: &Path) -> u64 {
+    let path = redpen_dir.join("epoch");
+    if let Some(epoch) = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+    {
+        return epoch;
+    }
+    let epoch = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+    let written =
+        std::fs::create_dir_all(redpen_dir).and_then(|_| std::fs::write(&path, epoch.to_string()));
+    if let Err(err) = written {
+        eprintln!("error: failed to write `{}`: {err}", path.display());
+        exit(1);
+    }
+    epoch
+}
+
+/// Metadata emitted by `cargo check` carries no optimized MIR, so ask rustc to encode it
+/// for every crate in order to let redpen walk into bodies from dependencies. It's added
+/// here rather than to `RUSTFLAGS`, which would override rustflags in cargo config.
+/// Probes like `rustc -vV` are left alone.
+fn encode_mir(rustc_args: &[String]) -> Vec<String> {
+    const ALWAYS_ENCODE_MIR: &str = "-Zalways-encode-mir";
+
+    let mut args = rustc_args.to_vec();
+    if var("CARGO_PKG_NAME").is_ok() && !args.iter().any(|arg| arg == ALWAYS_ENCODE_MIR) {
+        args.push(ALWAYS_ENCODE_MIR.to_owned());
+    }
+    args
+}
+
+/// How a crate compiled by cargo is handled.
+#[derive(Debug, PartialEq, Eq)]
+enum Target {
+    /// Packages selected by `-p`/`--workspace`/`--exclude` or the default workspace
+    /// members, which cargo marks with `CARGO_PRIMARY_PACKAGE`.
+    Analyzed,
+    /// Other crates, unless `--no-summarize-deps`, which only write summaries for analyzed
+    /// crates to load.
+    Summarized,
+    /// Other crates with `--no-summarize-deps`, build scripts, dependent proc macros,
+    /// sysroot crates built by `-Zbuild-std`, and probes like `rustc -vV`, compiled by the
+    /// real rustc.
+    Compiled,
+}
+
+/// Classify the crate compiled with the rustc arguments, given cargo's environment variables.
+fn target(rustc_args: &[String], env: impl Fn(&str) -> Option<String>) -> Target {
+    let has_arg = |name: &str, value: &str| {
+        rustc_args.windows(2).any(|w| w[0] == name && w[1] == value)
+            || rustc_args.iter().any(|arg| {
+                arg.strip_prefix(name)
+                    .and_then(|arg| arg.strip_prefix('='))
+                    .is_some_and(|arg| arg == value)
+            })
+    };
+    let is_build_script = has_arg("--crate-name", "build_script_build");
+    // Cargo marks crates built by `-Zbuild-std` with this flag.
+    let is_sysroot = has_arg("-Z", "force-unstable-if-unmarked")
+        || rustc_args
+            .iter()
+            .any(|arg| arg == "-Zforce-unstable-if-unmarked");
+    if env("CARGO_PRIMARY_PACKAGE").is_some() && !is_build_script {
+        Target::Analyzed
+    } else if env("CARGO_PKG_NAME").is_none()
+        || is_build_script
+        || is_sysroot
+        || has_arg("--crate-type", "proc-macro")
+        || env(ENV_SUMMARIZE_DEPS).as_deref() != Some("1")
+    {
+        Target::Compiled
+    } else {
+        Target::Summarized
+    }
+}
+
+/// Run cargo with `--message-format=json-*`, and collect the compiled artifacts.
+/// Other lines on stdout, like findings printed by the driver in JSON, are passed on.
+fn run_with_artifacts(cmd: &str, args: &[String], vars: &[(&str, &str)]) -> Vec<Artifact> {
+    #[derive(serde::Deserialize)]
+    struct Message {
+        reason: String,
+        #[serde(default)]
+        package_id: String,
+        #[serde(default)]
+        filenames: Vec<PathBuf>,
+    }
+
+    let mut child = Command::new(cmd)
+        .args(args)
+        .envs(vars.iter().copied())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|err| {
+            eprintln!("error: failed to run `{cmd}`: {err}");
+            exit(1);
+        });
+    let mut artifacts = Vec::new();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    for line in stdout.lines().map_while(Result::ok) {
+        match serde_json::from_str::<Message>(&line) {
+            Ok(msg) if msg.reason == "compiler-artifact" => artifacts.push(Artifact {
+                package_id: msg.package_id,
+                filenames: msg.filenames,
+            }),
+            Ok(_) => (),
+            Err(_) => println!("{line}"),
+        }
+    }
+    let status = child.wait().unwrap_or_else(|err| {
+        eprintln!("error: failed to wait for `{cmd}`: {err}");
+        exit(1);
+    });
+    if !status.success() {
+        exit(status.code().unwrap_or(1));
+    }
+    artifacts
+}
+
+/// Run a command, and exit with its status code if it fails.
+fn run(cmd: &str, args: &[String], vars: &[(&str, &str)]) {
+    let status = Command::new(cmd)
+        .args(args)
+        .envs(vars.iter().copied())
+        .status()
+        .unwrap_or_else(|err| {
+            eprintln!("error: failed to run `{cmd}`: {err}");
+            exit(1);
+        });
+    if !status.success() {
+        exit(status.code().unwrap_or(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PKG: &str = "CARGO_PKG_NAME";
+    const PRIMARY: &str = "CARGO_PRIMARY_PACKAGE";
+
+    fn classify(rustc_args: &str, vars: &[&str]) -> Target {
+        let args: Vec<_> = rustc_args.split_whitespace().map(str::to_owned).collect();
+        target(&args, |name| {
+            vars.contains(&name).then(|| {
+                if name == ENV_SUMMARIZE_DEPS {
+                    "1".to_owned()
+                } else {
+                    "utils".to_owned()
+                }
+            })
+        })
+    }
+
+    #[test]
+    fn primary_packages_are_analyzed() {
+        let lib = "--crate-name utils --edition=2021 src/lib.rs --crate-type lib";
+        assert_eq!(classify(lib, &[PKG, PRIMARY]), Target::Analyzed);
+        assert_eq!(
+            classify(lib, &[PKG, PRIMARY, ENV_SUMMARIZE_DEPS]),
+            Target::Analyzed
+        );
+        // Build scripts of primary packages are only compiled.
+        let build_script = "--crate-name build_script_build build.rs --crate-type bin";
+        assert_eq!(classify(build_script, &[PKG, PRIMARY]), Target::Compiled);
+        assert_eq!(
+            classify("--crate-name=build_script_build build.rs", &[PKG, PRIMARY]),
+            Target::Compiled
+        );
+    }
+
+    #[test]
+    fn dependencies_are_summarized() {
+        let dep = "--crate-name serde src/lib.rs --crate-type lib";
+        assert_eq!(classify(dep, &[PKG]), Target::Compiled);
+        assert_eq!(
+            classify(dep, &[PKG, ENV_SUMMARIZE_DEPS]),
+            Target::Summarized
+        );
+
+        let proc_macro = "--crate-name serde_derive src/lib.rs --crate-type proc-macro";
+        assert_eq!(
+            classify(proc_macro, &[PKG, ENV_SUMMARIZE_DEPS]),
+            Target::Compiled
+        );
+        let build_std = "--crate-name core src/lib.rs -Zforce-unstable-if-unmarked";
+        assert_eq!(
+            classify(build_std, &[PKG, ENV_SUMMARIZE_DEPS]),
+            Target::Compiled
+        );
+        let build_std = "--crate-name alloc src/lib.rs -Z force-unstable-if-unmarked";
+        assert_eq!(
+            classify(build_std, &[PKG, ENV_SUMMARIZE_DEPS]),
+            Target::Compiled
+        );
+    }
+
+    #[test]
+    fn probes_are_compiled() {
+        assert_eq!(classify("-vV", &[ENV_SUMMARIZE_DEPS]), Target::Compiled);
+        assert_eq!(
+            classify("- --crate-name ___ --print=file-names", &[]),
+            Target::Compiled
+        );
+    }
+
+    #[test]
+    fn target_dir_is_replaced() {
+        let split = |args: &str| {
+            let args: Vec<_> = args.split_whitespace().map(str::to_owned).collect();
+            split_target_dir(&args)
+        };
+        assert_eq!(
+            split("-p app"),
+            (None, vec!["-p".to_owned(), "app".to_owned()])
+        );
+        assert_eq!(
+            split("--target-dir out -p app"),
+            (Some("out".into()), vec!["-p".to_owned(), "app".to_owned()])
+        );
+        assert_eq!(
+            split("--tests --target-dir=out"),
+            (Some("out".into()), vec!["--tests".to_owned()])
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Results of analyzed crates, stored under `<dir>/<crate>-<svh>-<key>.json` and replayed
+//! when a crate is compiled again without changes, e.g. when a dependent crate is touched
+//! or options only differ in output format.
+//!
+//! The SVH covers the crate source and its dependencies, and the key covers the redpen
+//! version, the options affecting findings, like whether they carry witness paths, and the
+//! config text. Unused entries are left behind until `cargo redpen clean`.
+
+use crate::config::Config;
+use redpen::{
+    options::Options,
+    report::{CrateReport, fnv1a},
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::def_id::LOCAL_CRATE;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+pub struct Cache {
+    path: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: &Path, tcx: TyCtxt, config: &Config, opts: &Options) -> Self {
+        // Hashed with FNV-1a, so the key stays the same across builds of redpen.
+        let key = serde_json::to_string(&(
+            env!("CARGO_PKG_VERSION"),
+            &opts.checks,
+            opts.entries,
+            opts.skip_tests,
+            opts.root_causes,
+            opts.witnesses(),
+            &config.source,
+        ))
+        .unwrap();
+        let name = format!(
+            "{}-{}-{:016x}.json",
+            tcx.crate_name(LOCAL_CRATE),
+            tcx.crate_hash(LOCAL_CRATE),
+            fnv1a(key.as_bytes())
+        );
+        Cache {
+            path: dir.join(name),
+        }
+    }
+
+    /// Results stored by a previous run, if any.
+    pub fn load(&self) -> Option<CrateReport> {
+        let json = std::fs::read_to_string(&self.path).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    pub fn store(&self, report: &CrateReport) {
+        write_json(&self.path, report, "cache");
+    }
+}
+
+/// Write a JSON file, warning about failures. The file is written to a temporary file of
+/// this process first, so readers never see partial contents, even with other processes
+/// writing the same file.
+pub fn write_json(path: &Path, value: &impl Serialize, what: &str) {
+    let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&tmp, serde_json::to_string(value).unwrap()))
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if let Err(err) = written {
+        eprintln!(
+            "warning: failed to write redpen {what} `{}`: {err}",
+            path.display()
+        );
+    }
+}
@@ -1,28 +1,44 @@
-use crate::{detect::Detect, diagnostics::PanicSpots, fn_item::FnItem};
+use crate::{
+    detect::{self, Detect, RuleKind},
+    diagnostics::{SinkSpots, Witness},
+    fn_item::FnItem,
+    summary::{FnSummary, Summaries},
+};
 use indexmap::{IndexMap, IndexSet};
+use redpen::report::PanicKind;
 use rustc_middle::ty::TyCtxt;
 use rustc_public::{
-    mir::{Body, MirVisitor, Operand, TerminatorKind, visit::Location},
-    rustc_internal::internal,
+    mir::{Body, MirVisitor, Operand, Terminator, TerminatorKind, visit::Location},
+    rustc_internal::{internal, stable},
     ty::{FnDef, RigidTy, Span, Ty, TyKind},
 };
+use std::collections::VecDeque;
 
 #[derive(Debug, Default)]
 pub struct CallGraph {
     edges: IndexMap<FnItem, Nodes>,
     back_edges: IndexMap<FnItem, Nodes>,
+    /// Upstream fns reached in the graph whose callees are known from summaries only.
+    summarized: IndexMap<FnItem, FnSummary>,
 }
 
 impl CallGraph {
     /// Reach crate function items as entries.
-    pub fn reach_in_depth(&mut self, fn_item: FnItem) {
+    pub fn reach_in_depth(&mut self, fn_item: FnItem, summaries: &Summaries) {
         if self.edges.contains_key(&fn_item) {
             // The fn item has been reached before.
             return;
         }
 
+        if let Some(summary) = summaries.get(&fn_item) {
+            // Stop at fns summarized by upstream crates, instead of walking their bodies.
+            self.summarized.insert(fn_item.clone(), summary.clone());
+            self.edges.insert(fn_item, Nodes::default());
+            return;
+        }
+
         let mut nodes = Nodes::default();
-        if let Some(body) = fn_item.def.body() {
+        if let Some(body) = fn_item.body() {
             nodes.visit_body(&body);
         }
 
@@ -44,7 +60,7 @@ impl CallGraph {
 
         for callee in callees {
             // Recurse.
-            self.reach_in_depth(callee);
+            self.reach_in_depth(callee, summaries);
         }
     }
 
@@ -52,89 +68,343 @@ impl CallGraph {
     pub fn sort(&mut self) {
         self.edges.sort_by(|f1, _, f2, _| f1.cmp(f2));
         self.back_edges.sort_by(|f1, _, f2, _| f1.cmp(f2));
+        self.summarized.sort_by(|f1, _, f2, _| f1.cmp(f2));
         for node in self.edges.values_mut().chain(self.back_edges.values_mut()) {
             node.set.sort_by(|f1, f2| f1.cmp(f2));
         }
     }
 
+    /// All reached fn items.
+    pub fn fn_items(&self) -> impl Iterator<Item = &FnItem> {
+        self.edges.keys()
+    }
+
+    /// Summary of an upstream fn, which has no callees in the graph.
+    pub fn summary(&self, fn_item: &FnItem) -> Option<&FnSummary> {
+        self.summarized.get(fn_item)
+    }
+
+    /// All reached upstream fns with summaries.
+    pub fn summarized(&self) -> impl Iterator<Item = (&FnItem, &FnSummary)> {
+        self.summarized.iter()
+    }
+
     /// Search FnItem/DefId.
     pub fn get_fn_item(&self, fn_name: &str) -> Option<&FnItem> {
         self.edges.keys().find(|f| f.is(fn_name))
     }
 
-    fn call_path(&self, start: &FnItem, stop: &FnItem) -> CallPaths {
-        let mut path = Vec::new();
-        let mut v_path = Vec::new();
+    /// Direct callees of a fn item.
+    pub fn callees(&self, fn_item: &FnItem) -> impl Iterator<Item = &FnItem> {
+        self.edges.get(fn_item).into_iter().flat_map(|n| &n.set)
+    }
 
-        path.push(start.clone());
-        self.add_call_path(start, stop, &mut path, &mut v_path);
+    /// Fn items reachable from the given entries, including the entries themselves.
+    pub fn reachable_from<'a>(&'a self, entries: &'a [FnItem]) -> IndexSet<&'a FnItem> {
+        let mut reached = IndexSet::new();
+        let mut stack: Vec<_> = entries.iter().collect();
+        while let Some(f) = stack.pop() {
+            if reached.insert(f) {
+                stack.extend(self.callees(f));
+            }
+        }
+        reached
+    }
 
-        v_path
+    /// Strongly connected components among the given nodes, computed by Tarjan's algorithm.
+    /// Edges leaving the node set are ignored.
+    pub fn sccs<'a>(&'a self, nodes: &IndexSet<&'a FnItem>) -> Vec<Vec<&'a FnItem>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            nodes,
+            index: IndexMap::new(),
+            stack: Vec::new(),
+            on_stack: IndexSet::new(),
+            sccs: Vec::new(),
+        };
+        for node in nodes {
+            if !tarjan.index.contains_key(node) {
+                tarjan.connect(node);
+            }
+        }
+        tarjan.sccs
     }
 
-    fn add_call_path(
-        &self,
-        start: &FnItem,
-        stop: &FnItem,
-        path: &mut Vec<FnItem>,
-        v_path: &mut CallPaths,
-    ) {
-        if let Some(callees) = self.edges.get(start) {
-            for callee in &callees.set {
-                path.push(callee.clone());
-                if callee == stop {
-                    v_path.push(path.clone());
-                } else {
-                    self.add_call_path(callee, stop, path, v_path);
-                    path.pop();
+    /// Fn items that may reach any of the sinks, including the sinks themselves.
+    pub fn reaching<'a>(
+        &'a self,
+        sinks: impl IntoIterator<Item = &'a FnItem>,
+    ) -> IndexSet<&'a FnItem> {
+        let mut reached = IndexSet::new();
+        let mut stack: Vec<_> = sinks.into_iter().collect();
+        while let Some(f) = stack.pop() {
+            if reached.insert(f) {
+                stack.extend(self.back_edges.get(f).into_iter().flat_map(|n| &n.set));
+            }
+        }
+        reached
+    }
+
+    /// Fn items that may reach any of the sinks, with shortest call paths to them found by
+    /// one search backward from all the sinks.
+    pub fn sink_paths<'a>(&'a self, sinks: impl IntoIterator<Item = &'a FnItem>) -> SinkPaths<'a> {
+        let mut next: IndexMap<&FnItem, Option<&FnItem>> =
+            sinks.into_iter().map(|f| (f, None)).collect();
+        let mut queue: VecDeque<_> = next.keys().copied().collect();
+        while let Some(f) = queue.pop_front() {
+            for caller in self.back_edges.get(f).into_iter().flat_map(|n| &n.set) {
+                if !next.contains_key(caller) {
+                    next.insert(caller, Some(f));
+                    queue.push_back(caller);
                 }
             }
         }
+        SinkPaths { next }
     }
 
-    fn reachable(&self, start: &FnItem, stop: &FnItem) -> bool {
-        if let Some(callees) = self.edges.get(start) {
-            for callee in &callees.set {
-                if callee == stop || self.reachable(callee, stop) {
-                    return true;
+    /// The shortest call path from `start` to any of the sinks.
+    pub fn shortest_path(&self, start: &FnItem, sinks: &IndexSet<&FnItem>) -> Option<Vec<FnItem>> {
+        let (start, _) = self.edges.get_key_value(start)?;
+        let mut callers: IndexMap<&FnItem, Option<&FnItem>> = IndexMap::from([(start, None)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(f) = queue.pop_front() {
+            if sinks.contains(f) {
+                let mut path = vec![f.clone()];
+                let mut node = f;
+                while let Some(caller) = callers[node] {
+                    path.push(caller.clone());
+                    node = caller;
                 }
+                path.reverse();
+                return Some(path);
             }
+            for callee in self.callees(f) {
+                if !callers.contains_key(callee) {
+                    callers.insert(callee, Some(f));
+                    queue.push_back(callee);
+                }
+            }
+        }
+        None
+    }
+
+    /// Names of fns on a path to a sink, continued with the witness of a summarized
+    /// upstream sink of the set `key`.
+    pub fn witness(&self, path: &[FnItem], key: &str) -> Vec<String> {
+        let mut names: Vec<String> = path.iter().map(|f| f.qualified_name()).collect();
+        if let Some(witness) = path
+            .last()
+            .and_then(|last| self.summary(last))
+            .and_then(|s| s.witness(key))
+        {
+            names.extend(witness.iter().skip(1).cloned());
         }
-        false
+        names
     }
 
-    pub fn analyze(&self, detect: &Detect, tcx: TyCtxt) -> PanicSpots {
-        // dbg!(self);
-        let verbose = true;
-        let mut spots = PanicSpots::default();
+    /// Kind of the panic a path to a panic sink ends in, from the last fn on the path
+    /// telling one, or the summary of the upstream fn ending it.
+    pub fn panic_kind(&self, path: &[FnItem], tcx: TyCtxt) -> PanicKind {
+        path.iter()
+            .rev()
+            .find_map(|f| {
+                self.summary(f)
+                    .and_then(FnSummary::panic_kind)
+                    .or_else(|| detect::panic_kind(f, tcx))
+            })
+            .unwrap_or(PanicKind::Explicit)
+    }
 
-        let mut v_panic = Vec::new();
-        detect.with_panic_item(|f| v_panic.push(f.clone()));
-        if v_panic.is_empty() {
-            return spots;
-        };
+    /// Spots of each rule in entries, or in root causes if `root_causes` is set: local fns
+    /// calling a sink or an upstream fn reaching one, which are reported once with the
+    /// entries reaching them instead of at every entry on the way.
+    ///
+    /// Callees at spots are named with their witness paths to sinks only if `witnesses` is
+    /// set. Panic kinds at the end of the paths are always found, as they make up ids.
+    pub fn analyze<'r>(
+        &self,
+        detect: &'r Detect,
+        root_causes: bool,
+        witnesses: bool,
+        tcx: TyCtxt,
+    ) -> Vec<SinkSpots<'r>> {
+        let mut v_spots = Vec::new();
+        let entries: IndexSet<&FnItem> = detect.entries().iter().collect();
+        let exported: IndexSet<&FnItem> = detect.exported().iter().collect();
+
+        for rule in detect.rules() {
+            if rule.sinks().next().is_none() {
+                continue;
+            };
+            let mut spots = SinkSpots::new(rule);
+            let sink_paths = self.sink_paths(rule.sinks());
+            let may_reach: IndexSet<FnDef> = sink_paths.fn_items().map(|f| f.def).collect();
+            let key = rule.key();
+            let is_panic = matches!(rule.kind, RuleKind::Panic);
+            let witness = |callee: &FnItem| {
+                let path = sink_paths
+                    .path(callee)
+                    .unwrap_or_else(|| vec![callee.clone()]);
+                let witness = Witness {
+                    path: if witnesses {
+                        self.witness(&path, &key)
+                    } else {
+                        Vec::new()
+                    },
+                    panic_kind: is_panic.then(|| self.panic_kind(&path, tcx)),
+                };
+                (callee.qualified_name(), witness)
+            };
+            let witnesses = |local_spots: &LocalSinkSpot| -> Vec<(String, Witness)> {
+                local_spots.callees().iter().map(witness).collect()
+            };
 
-        for entry in detect.entries() {
-            for panic in &v_panic {
-                if !verbose && self.reachable(entry, panic) {
-                    println!("{entry:?} reaches panic");
-                } else {
+            if !root_causes {
+                for entry in detect.entries() {
                     let caller = entry.def;
-                    let Some(body) = caller.body() else { continue };
+                    if !may_reach.contains(&caller) {
+                        continue;
+                    }
+                    let Some(body) = entry.body() else { continue };
                     let span = body.span;
 
-                    let path = self.call_path(entry, panic);
-                    let mut local_spots = LocalPanicSpot::new(&path, &body, detect, tcx);
+                    let mut local_spots = LocalSinkSpot::new(&may_reach, &body, tcx);
                     local_spots.visit_body(&body);
-                    spots.add(caller, span, local_spots.panic_spots());
+                    let witnesses = witnesses(&local_spots);
+                    spots.add(entry.clone(), span, local_spots.sink_spots(), witnesses);
+                }
+                v_spots.push(spots);
+                continue;
+            }
+
+            let sinks: IndexSet<FnDef> = rule.sinks().map(|f| f.def).collect();
+            let roots = self.fn_items().filter(|f| {
+                f.is_local() && sink_paths.contains(f) && !f.has_redpen_attr("silence_panic")
+            });
+            for root in roots {
+                let Some(body) = root.body() else { continue };
+                let callers = self.reaching([root]);
+                if !callers.iter().any(|f| entries.contains(f)) {
+                    // Not reachable from any entry.
+                    continue;
                 }
+                let mut reached_from: Vec<FnItem> = callers
+                    .into_iter()
+                    .filter(|f| *f != root && exported.contains(f))
+                    .cloned()
+                    .collect();
+                reached_from.sort();
+                let mut local_spots = LocalSinkSpot::new(&may_reach, &body, tcx);
+                local_spots.visit_body(&body);
+                local_spots.retain_roots(&sinks);
+                let witnesses = witnesses(&local_spots);
+                spots.add_root(
+                    root.clone(),
+                    body.span,
+                    local_spots.sink_spots(),
+                    witnesses,
+                    reached_from,
+                );
             }
+            v_spots.push(spots);
         }
-        spots
+        v_spots
     }
 }
 
-type CallPaths = Vec<Vec<FnItem>>;
+/// Fn items reaching a set of sinks, each with the next fn on a shortest call path to a
+/// sink, or none for the sinks.
+pub struct SinkPaths<'a> {
+    next: IndexMap<&'a FnItem, Option<&'a FnItem>>,
+}
+
+impl<'a> SinkPaths<'a> {
+    pub fn contains(&self, fn_item: &FnItem) -> bool {
+        self.next.contains_key(fn_item)
+    }
+
+    pub fn fn_items(&self) -> impl Iterator<Item = &'a FnItem> + '_ {
+        self.next.keys().copied()
+    }
+
+    /// A shortest call path from `start` to a sink.
+    pub fn path(&self, start: &FnItem) -> Option<Vec<FnItem>> {
+        let (f, mut next) = self.next.get_key_value(start)?;
+        let mut path = vec![(*f).clone()];
+        while let Some(f) = next {
+            path.push((*f).clone());
+            next = &self.next[f];
+        }
+        Some(path)
+    }
+}
+
+struct Tarjan<'a, 'n> {
+    graph: &'a CallGraph,
+    nodes: &'n IndexSet<&'a FnItem>,
+    /// Visiting index and lowlink of each visited node.
+    index: IndexMap<&'a FnItem, (usize, usize)>,
+    stack: Vec<&'a FnItem>,
+    on_stack: IndexSet<&'a FnItem>,
+    sccs: Vec<Vec<&'a FnItem>>,
+}
+
+impl<'a> Tarjan<'a, '_> {
+    /// Visit nodes reachable from the root depth-first, with an explicit stack of callers
+    /// and their remaining callees, since deep call chains would overflow the native stack.
+    fn connect(&mut self, root: &'a FnItem) {
+        let mut frames = vec![self.visit(root)];
+        while let Some((node, callees)) = frames.last_mut() {
+            let node = *node;
+            if let Some(callee) = callees.next() {
+                match self.index.get(callee) {
+                    None => frames.push(self.visit(callee)),
+                    Some(&(callee_idx, _)) if self.on_stack.contains(callee) => {
+                        self.lower(node, callee_idx)
+                    }
+                    Some(_) => (),
+                }
+                continue;
+            }
+
+            frames.pop();
+            let (idx, lowlink) = self.index[node];
+            if let Some(&(caller, _)) = frames.last() {
+                self.lower(caller, lowlink);
+            }
+            if lowlink == idx {
+                let mut scc = Vec::new();
+                while let Some(f) = self.stack.pop() {
+                    self.on_stack.swap_remove(f);
+                    scc.push(f);
+                    if f == node {
+                        break;
+                    }
+                }
+                self.sccs.push(scc);
+            }
+        }
+    }
+
+    /// Assign the next index to the node, and return it with its callees in the node set.
+    fn visit(&mut self, node: &'a FnItem) -> (&'a FnItem, std::vec::IntoIter<&'a FnItem>) {
+        let idx = self.index.len();
+        self.index.insert(node, (idx, idx));
+        self.stack.push(node);
+        self.on_stack.insert(node);
+        let callees: Vec<_> = self
+            .graph
+            .callees(node)
+            .filter(|callee| self.nodes.contains(callee))
+            .collect();
+        (node, callees.into_iter())
+    }
+
+    fn lower(&mut self, node: &'a FnItem, lowlink: usize) {
+        let low = &mut self.index[node].1;
+        *low = (*low).min(lowlink);
+    }
+}
 
 #[derive(Debug, Default)]
 struct Nodes {
@@ -157,91 +427,134 @@ pub fn contains_span(tcx: TyCtxt, large: Span, small: Span) -> bool {
     large.contains(small)
 }
 
-struct LocalPanicSpot<'tcx, 'body, 'detect> {
+/// Spans of calls to any of the callees in a body.
+pub fn call_sites(tcx: TyCtxt, body: &Body, callees: &IndexSet<FnDef>) -> IndexSet<Span> {
+    let mut visitor = CallSites {
+        tcx,
+        body,
+        callees,
+        spans: IndexSet::new(),
+    };
+    visitor.visit_body(body);
+    visitor.spans
+}
+
+struct CallSites<'tcx, 'a> {
+    tcx: TyCtxt<'tcx>,
+    body: &'a Body,
+    callees: &'a IndexSet<FnDef>,
+    spans: IndexSet<Span>,
+}
+
+impl MirVisitor for CallSites<'_, '_> {
+    fn visit_terminator(&mut self, term: &Terminator, location: Location) {
+        if let TerminatorKind::Call { func, .. } = &term.kind
+            && let Ok(ty) = func.ty(self.body.locals())
+            && let Some((fn_def, _)) = ty.kind().fn_def()
+            && self.callees.contains(&fn_def)
+            && contains_span(self.tcx, self.body.span, location.span())
+        {
+            self.spans.insert(location.span());
+        }
+        self.super_terminator(term, location);
+    }
+}
+
+/// The span if it's in the body, or the macro call it's expanded from, like `panic!(..)`
+/// expanded to a call to `core::panicking::panic_fmt`.
+pub fn local_span(tcx: TyCtxt, body: Span, span: Span) -> Option<Span> {
+    if contains_span(tcx, body, span) {
+        return Some(span);
+    }
+    let callsite = stable(internal(tcx, span).source_callsite());
+    contains_span(tcx, body, callsite).then_some(callsite)
+}
+
+/// File and line/column range of a span. Distinct spans may share one, e.g. when they
+/// only differ in their macro expansion context, and would then be labelled twice.
+pub fn span_range(span: &Span) -> (String, usize, usize, usize, usize) {
+    let lines = span.get_lines();
+    (
+        span.get_filename(),
+        lines.start_line,
+        lines.start_col,
+        lines.end_line,
+        lines.end_col,
+    )
+}
+
+/// Spots in the body calling fns that may reach sinks, like [`CallGraph::analyze`] does for
+/// entries.
+pub fn sink_spots(tcx: TyCtxt, body: &Body, fn_may_reach: &IndexSet<FnDef>) -> IndexSet<Span> {
+    let mut local_spots = LocalSinkSpot::new(fn_may_reach, body, tcx);
+    local_spots.visit_body(body);
+    local_spots.sink_spots()
+}
+
+struct LocalSinkSpot<'tcx, 'body> {
     tcx: TyCtxt<'tcx>,
     caller_body: &'body Body,
-    detect: &'detect Detect,
-    fn_may_panic: IndexSet<FnDef>,
-    panic_spots: IndexMap<FnDef, Vec<Span>>,
-}
-
-impl<'tcx, 'body, 'detect> LocalPanicSpot<'tcx, 'body, 'detect> {
-    fn new(
-        path: &CallPaths,
-        body: &'body Body,
-        detect: &'detect Detect,
-        tcx: TyCtxt<'tcx>,
-    ) -> Self {
-        LocalPanicSpot {
+    fn_may_reach: &'body IndexSet<FnDef>,
+    sink_spots: IndexMap<FnDef, Vec<Span>>,
+}
+
+impl<'tcx, 'body> LocalSinkSpot<'tcx, 'body> {
+    fn new(fn_may_reach: &'body IndexSet<FnDef>, body: &'body Body, tcx: TyCtxt<'tcx>) -> Self {
+        LocalSinkSpot {
             tcx,
             caller_body: body,
-            detect,
-            fn_may_panic: path.iter().flatten().map(|a| a.def).collect(),
-            panic_spots: Default::default(),
+            fn_may_reach,
+            sink_spots: Default::default(),
         }
     }
 
-    fn contains(&self, span: Span) -> bool {
-        contains_span(self.tcx, self.caller_body.span, span)
+    fn sink_spots(self) -> IndexSet<Span> {
+        self.sink_spots.into_values().flatten().collect()
     }
 
-    fn panic_spots(self) -> IndexSet<Span> {
-        self.panic_spots.into_values().flatten().collect()
+    /// Fns called at sink spots.
+    fn callees(&self) -> Vec<FnItem> {
+        self.sink_spots
+            .keys()
+            .map(|&callee| callee.into())
+            .collect()
     }
 
-    fn check_panic_spot(&mut self, ty: &Ty, span: Span) {
+    /// Keep spots calling a sink, or an upstream fn that may reach one, but not a local fn
+    /// on the way to a sink, whose own spots are root causes.
+    fn retain_roots(&mut self, sinks: &IndexSet<FnDef>) {
+        self.sink_spots
+            .retain(|callee, _| !FnItem::from(*callee).is_local() || sinks.contains(callee));
+    }
+
+    fn check_sink_spot(&mut self, ty: &Ty, span: Span) {
         if let Some((fn_def, _)) = ty.kind().fn_def()
-            && self.fn_may_panic.contains(&fn_def)
-            && self.contains(span)
+            && self.fn_may_reach.contains(&fn_def)
+            && let Some(span) = self.local_span(span)
         {
             self.add(fn_def, span);
         }
     }
 
+    fn local_span(&self, span: Span) -> Option<Span> {
+        local_span(self.tcx, self.caller_body.span, span)
+    }
+
     fn add(&mut self, fn_def: FnDef, span: Span) {
-        self.panic_spots
-            .entry(fn_def)
-            .and_modify(|v| v.push(span))
-            .or_insert_with(|| vec![span]);
+        let spans = self.sink_spots.entry(fn_def).or_default();
+        let range = span_range(&span);
+        if !spans.iter().any(|sp| span_range(sp) == range) {
+            spans.push(span);
+        }
     }
 }
 
-impl MirVisitor for LocalPanicSpot<'_, '_, '_> {
+impl MirVisitor for LocalSinkSpot<'_, '_> {
     fn visit_operand(&mut self, operand: &Operand, location: Location) {
         if let Ok(ty) = operand.ty(self.caller_body.locals()) {
             let span = location.span();
-            self.check_panic_spot(&ty, span);
+            self.check_sink_spot(&ty, span);
         }
         self.super_operand(operand, location);
     }
-
-    fn visit_terminator(&mut self, term: &rustc_public::mir::Terminator, location: Location) {
-        // FIXME: the only reason to have this is to find the panic in `a`. But it's fragile,
-        // because `cargo run -- examples/check-panic/detected.rs --crate-type=lib --edition 2024`
-        // doesn't find the direct panic in `a`, while it's found with default 2015 edition.
-        if let TerminatorKind::Call { func, args, .. } = &term.kind
-            && let Ok(ty) = func.ty(self.caller_body.locals())
-            && let Some((fn_def, _)) = ty.kind().fn_def()
-            && self.detect.is_panic_fn(&fn_def)
-        {
-            for arg in args {
-                match arg {
-                    Operand::Copy(place) | Operand::Move(place) => {
-                        if let Some(decl) = self.caller_body.local_decl(place.local)
-                            && self.contains(decl.span)
-                        {
-                            self.add(fn_def, decl.span);
-                        }
-                    }
-                    Operand::Constant(const_operand) => {
-                        let span = const_operand.span;
-                        if self.contains(span) {
-                            self.add(fn_def, span);
-                        }
-                    }
-                }
-            }
-        }
-        self.super_terminator(term, location);
-    }
 }
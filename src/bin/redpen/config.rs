@@ -0,0 +1,79 @@
+use serde::Deserialize;
+use std::path::Path;
+
+/// Default configuration file, looked up in the current directory.
+const CONFIG_FILE: &str = "redpen.toml";
+
+/// Checks configured in `redpen.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Functions that must not be reached from entries.
+    #[serde(default)]
+    pub forbid: Vec<Forbid>,
+    #[serde(default)]
+    pub isr: Isr,
+    /// Text of the config file, which keys cached findings.
+    #[serde(skip)]
+    pub source: String,
+}
+
+/// A forbidden function or path pattern.
+///
+/// `path` is matched against fn paths without generic arguments. `*` matches any
+/// sequence of characters, and a path also matches every item under it, so
+/// `std::process` forbids `std::process::Command::new`. Local fns match with or without
+/// the crate name, like `module::f` or `my_crate::module::f`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Forbid {
+    pub path: String,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+/// Settings for interrupt handlers tagged with `#[redpen::isr]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Isr {
+    /// Path patterns of fns that must not be reached from handlers, in the same form as
+    /// [`Forbid::path`].
+    #[serde(default)]
+    pub non_reentrant: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+}
+
+impl Config {
+    /// Load the given config file, or `redpen.toml` if it exists.
+    pub fn load(path: Option<&Path>) -> Self {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(CONFIG_FILE).exists() => Path::new(CONFIG_FILE),
+            None => return Config::default(),
+        };
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| {
+                let config = toml::from_str(&text).map_err(|err| err.to_string())?;
+                Ok(Config {
+                    source: text,
+                    ..config
+                })
+            });
+        parsed.unwrap_or_else(|err| {
+            eprintln!(
+                "error: failed to load redpen config `{}`: {err}",
+                path.display()
+            );
+            std::process::exit(1);
+        })
+    }
+}
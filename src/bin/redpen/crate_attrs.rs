@@ -0,0 +1,71 @@
+use rustc_ast::ast;
+use rustc_span::Symbol;
+
+/// Flags to register the `redpen` tool, so `#[redpen::...]` attributes work on unmodified
+/// crates, and to set `cfg(redpen)` for `#[cfg_attr(redpen, redpen::...)]` which keeps
+/// crates building with plain rustc.
+const FLAGS: &[&str] = &[
+    "-Zcrate-attr=feature(register_tool)",
+    "-Zcrate-attr=register_tool(redpen)",
+    "--cfg=redpen",
+    "--check-cfg=cfg(redpen)",
+];
+
+/// Append [`FLAGS`] to rustc arguments, skipping those already given in either
+/// `-Zname=value` or `-Z name=value` form.
+///
+/// `--check-cfg` is only added when cfgs are checked already, as cargo does, since any
+/// `--check-cfg` turns on checking, which would warn about `cfg(test)` and the like.
+pub fn inject_flags(args: &mut Vec<String>) {
+    let mut given = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-Z" => given.extend(iter.next().map(|value| format!("-Z{value}"))),
+            "--cfg" | "--check-cfg" => {
+                given.extend(iter.next().map(|value| format!("{arg}={value}")))
+            }
+            _ => given.push(arg.clone()),
+        }
+    }
+    let checks_cfg = given.iter().any(|arg| arg.starts_with("--check-cfg="));
+    for flag in FLAGS {
+        if flag.starts_with("--check-cfg=") && !checks_cfg {
+            continue;
+        }
+        if !given.iter().any(|arg| arg == flag) {
+            args.push(flag.to_string());
+        }
+    }
+}
+
+/// The crate root may already enable `register_tool` or register `redpen` itself. Drop
+/// the later duplicates, i.e. ones injected by [`inject_flags`], which rustc would reject.
+pub fn dedup(krate: &mut ast::Crate) {
+    let mut has_feature = false;
+    let mut has_tool = false;
+    krate.attrs.retain(|attr| {
+        let seen = if provides(attr, "feature", "register_tool") {
+            &mut has_feature
+        } else if provides(attr, "register_tool", "redpen") {
+            &mut has_tool
+        } else {
+            return true;
+        };
+        let duplicated = *seen && is_single(attr);
+        *seen = true;
+        !duplicated
+    });
+}
+
+/// Whether the attribute is `#![name(.., word, ..)]`.
+fn provides(attr: &ast::Attribute, name: &str, word: &str) -> bool {
+    attr.has_name(Symbol::intern(name))
+        && attr
+            .meta_item_list()
+            .is_some_and(|list| list.iter().any(|item| item.has_name(Symbol::intern(word))))
+}
+
+fn is_single(attr: &ast::Attribute) -> bool {
+    attr.meta_item_list().is_some_and(|list| list.len() == 1)
+}
@@ -1,51 +1,288 @@
-use crate::{call_graph::CallGraph, fn_item::FnItem};
-use rustc_public::ty::FnDef;
+use crate::{
+    call_graph::CallGraph,
+    config::{Config, Severity},
+    fn_item::FnItem,
+    summary::summarized_sinks,
+};
+use redpen::{
+    options::{Check, Options},
+    report::PanicKind,
+};
+use rustc_hir::LangItem;
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{CrateDef, rustc_internal::internal};
+
+/// Key of the panic sink set in summaries.
+pub const PANIC: &str = "panic";
+
+/// Names of std fns that panics of each kind go through, besides lang items.
+const PANIC_FNS: &[(PanicKind, &[&str])] = &[
+    (
+        PanicKind::Bounds,
+        &[
+            "slice_index_fail",
+            "slice_start_index_len_fail",
+            "slice_end_index_len_fail",
+            "slice_index_order_fail",
+            "str_index_overflow_fail",
+            "slice_error_fail",
+        ],
+    ),
+    (PanicKind::Unwrap, &["unwrap_failed", "expect_failed"]),
+    (PanicKind::Assert, &["assert_failed"]),
+    (
+        PanicKind::Alloc,
+        &["capacity_overflow", "handle_alloc_error", "handle_error"],
+    ),
+    (
+        PanicKind::Borrow,
+        &["panic_already_borrowed", "panic_already_mutably_borrowed"],
+    ),
+    (PanicKind::Precondition, &["precondition_check"]),
+];
+
+/// Kind of the panics reached through a fn of core, alloc or std, if it tells one.
+pub fn panic_kind(f: &FnItem, tcx: TyCtxt) -> Option<PanicKind> {
+    if f.is_local() {
+        return None;
+    }
+    let def_id = internal(tcx, f.def.def_id());
+    if !matches!(
+        tcx.crate_name(def_id.krate).as_str(),
+        "core" | "alloc" | "std"
+    ) {
+        return None;
+    }
+    if let Some(item) = tcx.lang_items().from_def_id(def_id) {
+        match item {
+            LangItem::PanicBoundsCheck => return Some(PanicKind::Bounds),
+            LangItem::PanicAddOverflow
+            | LangItem::PanicSubOverflow
+            | LangItem::PanicMulOverflow
+            | LangItem::PanicDivOverflow
+            | LangItem::PanicRemOverflow
+            | LangItem::PanicNegOverflow
+            | LangItem::PanicShrOverflow
+            | LangItem::PanicShlOverflow
+            | LangItem::PanicDivZero
+            | LangItem::PanicRemZero => return Some(PanicKind::Arithmetic),
+            LangItem::PanicMisalignedPointerDereference
+            | LangItem::PanicNullPointerDereference
+            | LangItem::PanicInvalidEnumConstruction => return Some(PanicKind::Precondition),
+            _ => {}
+        }
+    }
+    let name = tcx.item_name(def_id);
+    PANIC_FNS
+        .iter()
+        .find(|(_, fns)| fns.contains(&name.as_str()))
+        .map(|(kind, _)| *kind)
+}
 
 pub struct Detect {
-    panic_nounwind: Option<FnItem>,
-    panic_nounwind_fmt: Option<FnItem>,
-    begin_panic: Option<FnItem>,
-    panic_fmt: Option<FnItem>,
+    rules: Vec<Rule>,
     entries: Vec<FnItem>,
+    /// Entries listed as reaching root causes.
+    exported: Vec<FnItem>,
 }
 
 impl Detect {
-    pub fn new(call_graph: &CallGraph, entries: Vec<FnItem>) -> Self {
+    pub fn new(
+        call_graph: &CallGraph,
+        entries: Vec<FnItem>,
+        exported: Vec<FnItem>,
+        config: &Config,
+        opts: &Options,
+        tcx: TyCtxt,
+    ) -> Self {
+        let mut rules = Vec::new();
+        if opts.enabled(Check::Panic) {
+            rules.push(Rule::panic(call_graph, tcx));
+        }
+        let forbid = if opts.enabled(Check::Forbid) {
+            &*config.forbid
+        } else {
+            &[]
+        };
+        rules.extend(forbid.iter().map(|forbid| {
+            Rule {
+                kind: RuleKind::Forbidden {
+                    path: forbid.path.clone(),
+                    message: forbid.message.clone(),
+                },
+                severity: forbid.severity,
+                sinks: call_graph
+                    .fn_items()
+                    .filter(|f| fn_matches(&forbid.path, f))
+                    .cloned()
+                    .collect(),
+                summarized: summarized_sinks(call_graph, &forbid_key(&forbid.path)),
+            }
+        }));
+        Detect {
+            rules,
+            entries,
+            exported,
+        }
+    }
+
+    pub fn entries(&self) -> &[FnItem] {
+        &self.entries
+    }
+
+    pub fn exported(&self) -> &[FnItem] {
+        &self.exported
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+}
+
+/// Functions to be reported when reached from entries.
+pub struct Rule {
+    pub kind: RuleKind,
+    pub severity: Severity,
+    sinks: Vec<FnItem>,
+    /// Upstream fns summarized to reach any of the sinks.
+    summarized: Vec<FnItem>,
+}
+
+pub enum RuleKind {
+    Panic,
+    Forbidden {
+        path: String,
+        message: Option<String>,
+    },
+}
+
+impl Rule {
+    pub fn panic(call_graph: &CallGraph, tcx: TyCtxt) -> Self {
         const PANIC_NOUNWIND: &str = "core::panicking::panic_nounwind";
         const PANIC_NOUNWIND_FMT: &str = "core::panicking::panic_nounwind_fmt";
         const BEGIN_PANIC: &str = "std::rt::begin_panic";
         const PANIC_FMT: &str = "std::rt::panic_fmt";
+        // The same fn, named so when std is not linked.
+        const CORE_PANIC_FMT: &str = "core::panicking::panic_fmt";
 
-        let get = |name: &str| call_graph.get_fn_item(name).cloned();
+        let mut sinks: Vec<FnItem> = [
+            PANIC_NOUNWIND,
+            PANIC_NOUNWIND_FMT,
+            BEGIN_PANIC,
+            PANIC_FMT,
+            CORE_PANIC_FMT,
+        ]
+        .iter()
+        .filter_map(|name| call_graph.get_fn_item(name).cloned())
+        .collect();
+        // Panics of `no_std` crates end up in the `#[panic_handler]`, which may be called
+        // directly too.
+        if let Some(handler) = tcx.lang_items().panic_impl() {
+            sinks.extend(
+                call_graph
+                    .fn_items()
+                    .filter(|f| internal(tcx, f.def.def_id()) == handler)
+                    .cloned(),
+            );
+        }
 
-        Detect {
-            panic_nounwind: get(PANIC_NOUNWIND),
-            panic_nounwind_fmt: get(PANIC_NOUNWIND_FMT),
-            begin_panic: get(BEGIN_PANIC),
-            panic_fmt: get(PANIC_FMT),
-            entries,
+        Rule {
+            kind: RuleKind::Panic,
+            severity: Severity::Error,
+            sinks,
+            summarized: summarized_sinks(call_graph, PANIC),
         }
     }
 
-    pub fn entries(&self) -> &[FnItem] {
-        &self.entries
+    /// Name of the sink set in summaries.
+    pub fn key(&self) -> String {
+        match &self.kind {
+            RuleKind::Panic => PANIC.to_owned(),
+            RuleKind::Forbidden { path, .. } => forbid_key(path),
+        }
+    }
+
+    /// Sinks and upstream fns summarized to reach them.
+    pub fn sinks(&self) -> impl Iterator<Item = &FnItem> + Clone {
+        self.sinks.iter().chain(&self.summarized)
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self.kind {
+            RuleKind::Panic => "A possible panic spot is found.",
+            RuleKind::Forbidden { .. } => "A use of forbidden function is found.",
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match &self.kind {
+            RuleKind::Panic => "This may panic!".to_owned(),
+            RuleKind::Forbidden { path, .. } => format!("This may call `{path}`."),
+        }
     }
 
-    pub fn with_panic_item(&self, mut f: impl FnMut(&FnItem)) {
-        let panic_items = [
-            &self.panic_nounwind,
-            &self.panic_nounwind_fmt,
-            &self.begin_panic,
-            &self.panic_fmt,
-        ];
-        for panic in panic_items.iter().filter_map(|&f| f.as_ref()) {
-            f(panic);
+    /// Extra explanation attached to each report.
+    pub fn note(&self) -> Option<&str> {
+        match &self.kind {
+            RuleKind::Panic => None,
+            RuleKind::Forbidden { message, .. } => message.as_deref(),
         }
     }
+}
+
+/// Key of the sink set of a `[[forbid]]` entry in summaries.
+pub fn forbid_key(path: &str) -> String {
+    format!("forbid:{path}")
+}
+
+/// Whether a fn matches a pattern. Local fns match with or without the crate name.
+pub fn fn_matches(pattern: &str, f: &FnItem) -> bool {
+    path_matches(pattern, &f.name) || f.is_local() && path_matches(pattern, &f.qualified_name())
+}
 
-    pub fn is_panic_fn(&self, fn_def: &FnDef) -> bool {
-        let mut b = false;
-        self.with_panic_item(|f| b |= f.def == *fn_def);
-        b
+/// Whether a fn path matches a pattern. See [`crate::config::Forbid`].
+pub fn path_matches(pattern: &str, fn_path: &str) -> bool {
+    let fn_path = strip_generic_args(fn_path);
+    glob_matches(pattern.as_bytes(), fn_path.as_bytes())
+        || fn_path
+            .match_indices("::")
+            .any(|(idx, _)| glob_matches(pattern.as_bytes(), &fn_path.as_bytes()[..idx]))
+}
+
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| glob_matches(rest, &text[i..])),
+        Some((c, rest)) => text
+            .split_first()
+            .is_some_and(|(t, text)| c == t && glob_matches(rest, text)),
+    }
+}
+
+/// Remove generic arguments like `::<T, A>` from a fn path, but keep `<impl u64>`.
+fn strip_generic_args(fn_path: &str) -> String {
+    let mut stripped = String::with_capacity(fn_path.len());
+    let mut rest = fn_path;
+    while let Some(start) = rest.find("::<") {
+        stripped.push_str(&rest[..start]);
+        let args = &rest[start + 2..];
+        let mut depth = 0;
+        let end = args
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    _ => (),
+                }
+                depth == 0
+            })
+            .map_or(args.len(), |(idx, _)| idx + 1);
+        if args.starts_with("<impl ") {
+            stripped.push_str(&rest[start..start + 2 + end]);
+        }
+        rest = &args[end..];
     }
+    stripped.push_str(rest);
+    stripped
 }
@@ -1,18 +1,25 @@
 extern crate rustc_span;
 
-use annotate_snippets::{AnnotationKind, Level, Renderer, Snippet};
+use crate::{
+    call_graph::span_range,
+    config::Severity,
+    detect::{Rule, RuleKind},
+    fn_item::FnItem,
+};
+use annotate_snippets::Renderer;
 use indexmap::{IndexMap, IndexSet};
-use rustc_middle::ty::TyCtxt;
-use rustc_public::{
-    CrateDef,
-    rustc_internal::internal,
-    ty::{FnDef, Span as PubSpan},
+use redpen::{
+    baseline::Baseline,
+    options::{Check, Format},
+    report::{Finding, Level, Location, PanicKind, Snippet},
 };
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{CrateDef, rustc_internal::internal, ty::Span as PubSpan};
 use rustc_span::{
     Span,
     source_map::{SourceMap, get_source_map},
 };
-use std::sync::Arc;
+use std::{cell::RefCell, fs::File, io::Write, ops::Range, path::PathBuf, sync::Arc};
 
 pub struct SourceCode<'tcx> {
     tcx: TyCtxt<'tcx>,
@@ -26,59 +33,172 @@ impl SourceCode<'_> {
             src_map: get_source_map().unwrap(),
         }
     }
+
+    /// Snippet of the whole function body, with the function header as context.
+    pub fn snippet(&self, body: PubSpan, header: PubSpan, label: &'static str) -> Snippet {
+        let span_func = span(body, self.tcx);
+        let source_map = &self.src_map;
+
+        let source = source_map.span_to_snippet(span_func).unwrap_or_else(|err| {
+            panic!("Unable to get snippet from this span `{span_func:?}`:\n{err:?}",)
+        });
+
+        let loc = self.origin(body);
+        Snippet::new(loc.file, loc.line, loc.column, source)
+            .context(self.offset(body, header), label)
+    }
+
+    /// Location of the start of a span, rendered as `--> file:line:col`.
+    pub fn origin(&self, sp: PubSpan) -> Location {
+        let loc = self.src_map.lookup_char_pos(span(sp, self.tcx).lo());
+        Location {
+            file: loc.file.name.prefer_remapped_unconditionally().to_string(),
+            line: loc.line,
+            column: loc.col_display + 1,
+        }
+    }
+
+    /// Byte range of `sp` relative to the start of `body`.
+    pub fn offset(&self, body: PubSpan, sp: PubSpan) -> Range<usize> {
+        let pos_func = span(body, self.tcx).lo();
+        let span = span(sp, self.tcx);
+        let call_span_lo = span.lo() - pos_func;
+        let call_span_hi = span.hi() - pos_func;
+        call_span_lo.0 as usize..call_span_hi.0 as usize
+    }
 }
 
-struct CheckPanic<'tcx, 'src, 'spots> {
-    f: PubSpan,
-    spots: &'spots Spots,
-    src: &'src SourceCode<'tcx>,
+/// Collects findings, and prints them sorted by location to stderr or stdout in the given
+/// format, also writing them as plain text to the report file if one is given. The file is
+/// only created for the first finding, and a stale one from a previous run is removed.
+/// Findings known in the baseline are kept, but not printed.
+pub struct Emitter {
+    format: Format,
+    report: Option<PathBuf>,
+    baseline: Baseline,
+    emitted: RefCell<Vec<Finding>>,
 }
 
-impl<'tcx, 'src, 'spots> CheckPanic<'tcx, 'src, 'spots> {
-    pub fn new(f: PubSpan, spots: &'spots Spots, src: &'src SourceCode<'tcx>) -> Self {
-        CheckPanic { f, spots, src }
+impl Emitter {
+    pub fn new(format: Format, report: Option<PathBuf>, baseline: Baseline) -> Self {
+        if let Some(path) = &report {
+            _ = std::fs::remove_file(path);
+        }
+        Emitter {
+            format,
+            report,
+            baseline,
+            emitted: RefCell::new(Vec::new()),
+        }
     }
 
-    pub fn emit(&self, renderer: &Renderer) {
-        let tcx = self.src.tcx;
-        let span_func = span(self.spots.caller, tcx);
-        let source_map = &self.src.src_map;
+    pub fn emit(&self, finding: Finding) {
+        self.emitted.borrow_mut().push(finding.fingerprinted());
+    }
 
-        let source = source_map.span_to_snippet(span_func).unwrap_or_else(|err| {
-            panic!("Unable to get snippet from this span `{span_func:?}`:\n{err:?}",)
-        });
+    /// Print findings emitted so far, and return all of them, including known ones.
+    pub fn finish(self) -> Vec<Finding> {
+        let mut findings = self.emitted.into_inner();
+        redpen::report::sort(&mut findings);
 
-        let pos_func = span_func.lo();
-        let loc = source_map.lookup_char_pos(pos_func);
-        let file_path = loc.file.name.prefer_remapped_unconditionally().to_string();
+        let mut file = None;
+        for finding in findings.iter().filter(|f| !self.baseline.contains(f)) {
+            match self.format {
+                Format::Human => eprintln!("{}", finding.render(&Renderer::styled())),
+                Format::Json => println!("{}", serde_json::to_string(finding).unwrap()),
+                Format::OsChecker => {
+                    println!("{}", serde_json::to_string(&finding.os_checker()).unwrap())
+                }
+            }
 
-        let offset = |sp: PubSpan| {
-            let span = span(sp, tcx);
-            let call_span_lo = span.lo() - pos_func;
-            let call_span_hi = span.hi() - pos_func;
-            call_span_lo.0 as usize..call_span_hi.0 as usize
-        };
+            if let Some(path) = &self.report {
+                let file = file.get_or_insert_with(|| {
+                    if let Some(dir) = path.parent() {
+                        _ = std::fs::create_dir_all(dir);
+                    }
+                    File::create(path).unwrap_or_else(|err| {
+                        panic!("Unable to create `{}`:\n{err:?}", path.display())
+                    })
+                });
+                _ = writeln!(file, "{}\n", finding.render(&Renderer::plain()));
+            }
+        }
+        findings
+    }
+}
+
+struct CheckSink<'tcx, 'src, 'spots> {
+    f: &'spots FnItem,
+    rule: &'spots Rule,
+    spots: &'spots Spots,
+    src: &'src SourceCode<'tcx>,
+}
+
+impl<'tcx, 'src, 'spots> CheckSink<'tcx, 'src, 'spots> {
+    pub fn new(
+        f: &'spots FnItem,
+        rule: &'spots Rule,
+        spots: &'spots Spots,
+        src: &'src SourceCode<'tcx>,
+    ) -> Self {
+        CheckSink {
+            f,
+            rule,
+            spots,
+            src,
+        }
+    }
 
-        let annot_caller = AnnotationKind::Context
-            .span(offset(self.f))
-            .label("For this function.");
+    pub fn emit(&self, out: &Emitter) {
+        let label = self.rule.label();
+        let annot_call = |sp: PubSpan| (self.src.offset(self.spots.caller, sp), label.clone());
 
-        let annot_call = |sp: PubSpan| {
-            AnnotationKind::Primary
-                .span(offset(sp))
-                .label("This may panic!")
+        let (check, level) = match (&self.rule.kind, self.rule.severity) {
+            (RuleKind::Panic, _) => (Check::Panic, Level::Error),
+            (RuleKind::Forbidden { .. }, Severity::Error) => (Check::Forbid, Level::Error),
+            (RuleKind::Forbidden { .. }, Severity::Warning) => (Check::Forbid, Level::Warning),
         };
-
-        let diag = Level::ERROR
-            .primary_title("A possible panic spot is found.")
-            .element(
-                Snippet::source(source)
-                    .path(file_path)
-                    .line_start(loc.line)
-                    .annotation(annot_caller)
-                    .annotations(self.spots.calls.iter().copied().map(annot_call)),
+        let mut finding = Finding::new(check, level, self.rule.title(), self.f.qualified_name());
+        if let RuleKind::Forbidden { path, .. } = &self.rule.kind {
+            finding = finding.about([path.clone()]);
+        }
+        let panic_kinds: Vec<PanicKind> = self
+            .spots
+            .witnesses
+            .values()
+            .filter_map(|witness| witness.panic_kind)
+            .collect();
+        finding = finding.about(self.spots.witnesses.keys().cloned());
+        if !panic_kinds.is_empty() {
+            // Tell apart panics at the same calls, like an unwrap from an out of bounds index.
+            finding = finding.about(panic_kinds.iter().map(|kind| kind.name()));
+        }
+        let finding = finding
+            .snippet(
+                self.src
+                    .snippet(self.spots.caller, self.f.def.span(), "For this function.")
+                    .primaries(self.spots.calls.iter().copied().map(annot_call)),
+            )
+            .witnesses(
+                self.spots
+                    .witnesses
+                    .values()
+                    .filter(|witness| !witness.path.is_empty())
+                    .map(|witness| {
+                        let mut path = vec![self.f.qualified_name()];
+                        path.extend(witness.path.iter().cloned());
+                        path
+                    }),
+            )
+            .panic_kinds(panic_kinds)
+            .notes(self.rule.note().map(str::to_owned))
+            .notes(
+                self.spots
+                    .reached_from
+                    .iter()
+                    .map(|entry| format!("Reached from `{}`", entry.qualified_name())),
             );
-        eprintln!("{}", renderer.render(&[diag]));
+        out.emit(finding);
     }
 }
 
@@ -86,48 +206,114 @@ impl<'tcx, 'src, 'spots> CheckPanic<'tcx, 'src, 'spots> {
 struct Spots {
     caller: PubSpan,
     calls: IndexSet<PubSpan>,
+    /// Paths of the fns called at the spots, each with its witness path to a sink.
+    witnesses: IndexMap<String, Witness>,
+    /// Entries reaching a root cause, reported as notes.
+    reached_from: Vec<FnItem>,
+}
+
+/// Call path from a fn called at a spot to a sink, by name, and the kind of panic it ends
+/// in for the panic rule. The path is empty when witnesses aren't computed, but the kind is
+/// always found.
+#[derive(Debug, Default)]
+pub struct Witness {
+    pub path: Vec<String>,
+    pub panic_kind: Option<PanicKind>,
 }
 
 fn span(sp: PubSpan, tcx: TyCtxt) -> Span {
     internal(tcx, sp)
 }
 
-#[derive(Default, Debug)]
-pub struct PanicSpots {
-    map: IndexMap<FnDef, Spots>,
+/// Keep the longest of spans starting at the same source position, like the call to
+/// `unwrap` in `lock().unwrap()` and the call to `lock` in it, which would otherwise be
+/// rendered as the same label stacked twice. Spans of the same range collapse to one.
+fn dedup_ranges(spans: &mut IndexSet<PubSpan>) {
+    let mut longest: IndexMap<_, (PubSpan, _)> = IndexMap::new();
+    for &sp in spans.iter() {
+        let (file, start_line, start_col, end_line, end_col) = span_range(&sp);
+        let end = (end_line, end_col);
+        longest
+            .entry((file, start_line, start_col))
+            .and_modify(|v| {
+                if end > v.1 {
+                    *v = (sp, end);
+                }
+            })
+            .or_insert((sp, end));
+    }
+    *spans = longest.into_values().map(|(sp, _)| sp).collect();
+}
+
+/// Spots in entries, or in root causes, that may reach sinks of a rule.
+pub struct SinkSpots<'rule> {
+    rule: &'rule Rule,
+    map: IndexMap<FnItem, Spots>,
 }
 
-impl PanicSpots {
-    pub fn add(&mut self, caller: FnDef, span_caller: PubSpan, mut span_callee: IndexSet<PubSpan>) {
+impl<'rule> SinkSpots<'rule> {
+    pub fn new(rule: &'rule Rule) -> Self {
+        SinkSpots {
+            rule,
+            map: IndexMap::new(),
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        caller: FnItem,
+        span_caller: PubSpan,
+        mut span_callee: IndexSet<PubSpan>,
+        witnesses: Vec<(String, Witness)>,
+    ) {
         if span_callee.is_empty() {
             return;
         }
         // Don't include the span of caller header.
-        span_callee.swap_remove(&caller.span());
+        span_callee.swap_remove(&caller.def.span());
         // Don't include the span of caller body.
         span_callee.swap_remove(&span_caller);
 
         if let Some(v) = self.map.get_mut(&caller) {
             v.calls.extend(span_callee);
+            dedup_ranges(&mut v.calls);
+            v.witnesses.extend(witnesses);
         } else {
+            dedup_ranges(&mut span_callee);
             self.map.insert(
                 caller,
                 Spots {
                     caller: span_caller,
                     calls: span_callee,
+                    witnesses: witnesses.into_iter().collect(),
+                    reached_from: Vec::new(),
                 },
             );
         }
     }
 
+    /// Add spots of a root cause, with entries reaching it.
+    pub fn add_root(
+        &mut self,
+        root: FnItem,
+        span_root: PubSpan,
+        span_callee: IndexSet<PubSpan>,
+        witnesses: Vec<(String, Witness)>,
+        reached_from: Vec<FnItem>,
+    ) {
+        self.add(root.clone(), span_root, span_callee, witnesses);
+        if let Some(v) = self.map.get_mut(&root) {
+            v.reached_from = reached_from;
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.map.is_empty()
     }
 
-    pub fn emit(&self, src: &SourceCode) {
-        let renderer = Renderer::styled();
+    pub fn emit(&self, src: &SourceCode, out: &Emitter) {
         for (f, calls) in &self.map {
-            CheckPanic::new(f.span(), calls, src).emit(&renderer);
+            CheckSink::new(f, self.rule, calls, src).emit(out);
         }
     }
 }
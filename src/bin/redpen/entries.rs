@@ -0,0 +1,61 @@
+use crate::{fn_item::FnItem, tests::TestFns};
+use redpen::options::{Entries, Options};
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{CrateDef, rustc_internal::internal};
+
+/// Decides which local fns are entries to report.
+pub struct EntryFilter<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    mode: Entries,
+    skip_tests: bool,
+    test_fns: TestFns<'tcx>,
+}
+
+impl<'tcx> EntryFilter<'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>, opts: &Options) -> Self {
+        EntryFilter {
+            tcx,
+            mode: opts.entries,
+            skip_tests: opts.skip_tests,
+            test_fns: TestFns::new(tcx),
+        }
+    }
+
+    pub fn is_entry(&self, fn_item: &FnItem) -> bool {
+        // When a top-level function is tagged, don't treat it as an entry item to report.
+        // Neither is the `main` generated for tests, nor tests themselves if skipped.
+        if fn_item.has_redpen_attr("silence_panic")
+            || self.test_fns.is_harness_main(fn_item)
+            || (self.skip_tests && self.test_fns.contains(fn_item))
+        {
+            return false;
+        }
+        self.selects(self.mode, fn_item)
+    }
+
+    /// Whether an entry is listed as reaching a root cause in `--redpen-root-causes` mode.
+    /// With `--redpen-entries=all`, where every fn is an entry, only the public API and
+    /// exports are, instead of every caller on the way.
+    pub fn is_exported(&self, entry: &FnItem) -> bool {
+        self.mode != Entries::All
+            || self.selects(Entries::Pub, entry)
+            || self.selects(Entries::Exports, entry)
+    }
+
+    fn selects(&self, mode: Entries, fn_item: &FnItem) -> bool {
+        let tcx = self.tcx;
+        let def_id = internal(tcx, fn_item.def.def_id());
+        match mode {
+            Entries::All => true,
+            Entries::Pub => def_id
+                .as_local()
+                .is_some_and(|def_id| tcx.effective_visibilities(()).is_exported(def_id)),
+            Entries::Exports => {
+                tcx.entry_fn(()).is_some_and(|(main, _)| main == def_id)
+                    || self.test_fns.contains(fn_item)
+                    || tcx.codegen_fn_attrs(def_id).contains_extern_indicator()
+            }
+            Entries::Tagged => fn_item.has_redpen_attr("entry"),
+        }
+    }
+}
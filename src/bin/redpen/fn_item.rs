@@ -1,5 +1,17 @@
-use rustc_public::{CrateDef, ty::FnDef};
-use std::{fmt, rc::Rc};
+use rustc_middle::{
+    mir::{self, ConstOperand, visit::Visitor},
+    ty::{InstanceKind, TyCtxt, TypingEnv, tls},
+};
+use rustc_public::{CrateDef, mir::Body, rustc_internal::internal, ty::FnDef};
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+/// Crates of the sysroot, which are not compiled by cargo.
+const SYSROOT_CRATES: &[&str] = &["std", "core", "alloc"];
+
+thread_local! {
+    /// Whether the body of each fn def can be translated by rustc_public.
+    static TRANSLATABLE: RefCell<HashMap<FnDef, bool>> = RefCell::default();
+}
 
 /// A FnDef simplified on Debug trait and `{:?}` printing.
 #[derive(Clone)]
@@ -51,4 +63,86 @@ impl FnItem {
     pub fn is(&self, name: &str) -> bool {
         *self.name == *name
     }
+
+    /// Path of the fn item including the crate name, which is omitted for local items.
+    pub fn qualified_name(&self) -> String {
+        if self.is_local() {
+            format!("{}::{}", self.def.krate().name, self.name)
+        } else {
+            self.name.to_string()
+        }
+    }
+
+    /// Whether the fn item is tagged with `#[redpen::{attr}]`.
+    pub fn has_redpen_attr(&self, attr: &str) -> bool {
+        let expected = format!("#[redpen::{attr}]");
+        self.def
+            .all_tool_attrs()
+            .iter()
+            .any(|a| a.as_str().trim() == expected)
+    }
+
+    /// MIR body of the fn item.
+    ///
+    /// rustc_public panics on translating constants whose layout depends on generic
+    /// parameters, like `None::<&T>` in `core::slice::<impl [T]>::first`. Such bodies are
+    /// treated as bodiless instead, and listed by [`skipped_bodies`].
+    pub fn body(&self) -> Option<Body> {
+        if !self.def.has_body() {
+            return None;
+        }
+        let translatable = TRANSLATABLE.with_borrow_mut(|translatable| {
+            *translatable.entry(self.def).or_insert_with(|| {
+                tls::with(|tcx| {
+                    let def_id = internal(tcx, self.def.def_id());
+                    let mut consts = LayoutConsts { tcx, ok: true };
+                    consts.visit_body(tcx.instance_mir(InstanceKind::Item(def_id)));
+                    consts.ok
+                })
+            })
+        });
+        if !translatable {
+            return None;
+        }
+        self.def.body()
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.def.krate().is_local
+    }
+
+    pub fn is_sysroot(&self) -> bool {
+        SYSROOT_CRATES.contains(&&*self.def.krate().name)
+    }
+}
+
+/// Fn items whose bodies have been skipped by [`FnItem::body`], sorted by name.
+pub fn skipped_bodies() -> Vec<FnItem> {
+    let mut skipped: Vec<_> = TRANSLATABLE.with_borrow(|translatable| {
+        translatable
+            .iter()
+            .filter(|(_, ok)| !**ok)
+            .map(|(def, _)| FnItem::new(*def))
+            .collect()
+    });
+    skipped.sort();
+    skipped
+}
+
+/// Checks that every evaluated constant in a body has a layout, which rustc_public needs to
+/// translate it into an allocation.
+struct LayoutConsts<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    ok: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for LayoutConsts<'tcx> {
+    fn visit_const_operand(&mut self, constant: &ConstOperand<'tcx>, _: mir::Location) {
+        if let mir::Const::Val(val, ty) = constant.const_
+            && !matches!(val, mir::ConstValue::ZeroSized)
+        {
+            let env = TypingEnv::fully_monomorphized();
+            self.ok &= self.tcx.layout_of(env.as_query_input(ty)).is_ok();
+        }
+    }
 }
@@ -0,0 +1,566 @@
+//! Export of the call graph, in DOT to render with Graphviz, or in JSON and GraphML for
+//! other tools.
+
+use crate::{
+    call_graph::{CallGraph, local_span},
+    detect::Rule,
+    diagnostics::SourceCode,
+    fn_item::FnItem,
+};
+use indexmap::{IndexMap, IndexSet};
+use redpen::{options::GraphFormat, report::Location};
+use rustc_middle::ty::{self as rustc_ty, TyCtxt};
+use rustc_public::{
+    CrateDef,
+    mir::{
+        Body, MirVisitor, Operand, Safety, Terminator, TerminatorKind, visit::Location as MirLoc,
+    },
+    rustc_internal::{internal, stable},
+    ty::{FnDef, GenericArgKind, GenericArgs, RigidTy, Span, Ty, TyKind},
+};
+use rustc_span::def_id::DefId;
+use serde::Serialize;
+use std::{collections::HashSet, fmt::Write, path::Path};
+
+/// The call graph, or the subgraph on call paths from an entry to panics.
+pub struct Graph<'g> {
+    call_graph: &'g CallGraph,
+    nodes: IndexSet<&'g FnItem>,
+    /// Fns that may reach a panic, including the sinks.
+    may_panic: IndexSet<&'g FnItem>,
+    sinks: IndexSet<&'g FnItem>,
+}
+
+#[derive(Serialize)]
+struct Export {
+    #[serde(rename = "crate")]
+    krate: String,
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Serialize)]
+struct Node {
+    path: String,
+    #[serde(rename = "crate")]
+    krate: String,
+    span: Location,
+    has_body: bool,
+    is_unsafe: bool,
+    may_panic: bool,
+    /// Only known from the summary of an upstream crate, so callees are not in the graph.
+    summarized: bool,
+}
+
+/// Calls from one node to another, by index in the nodes. Sites are empty when the callee
+/// is only named in types of the caller, like generic args.
+#[derive(Serialize)]
+struct Edge {
+    caller: usize,
+    callee: usize,
+    sites: Vec<Site>,
+}
+
+#[derive(Serialize)]
+struct Site {
+    #[serde(flatten)]
+    location: Location,
+    kind: CallKind,
+}
+
+/// How the callee is called at a site.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CallKind {
+    /// A call to a fn or an inherent method.
+    Direct,
+    /// A call to a trait method, resolved at compile time.
+    Trait,
+    /// A call to a trait method on a trait object.
+    Dyn,
+    /// A fn item used as a value, like passed as a closure or coerced to a fn pointer, and
+    /// maybe called later. Calls through fn pointers aren't resolved, so the edge is from
+    /// where the fn is named.
+    FnPtr,
+    /// A `Drop::drop` impl run by dropping a value, or an explicit call to `drop_in_place`.
+    Drop,
+}
+
+impl<'g> Graph<'g> {
+    /// The whole graph, or the subgraph from the local fn named by `entry`, if it's defined
+    /// in the crate.
+    pub fn new(call_graph: &'g CallGraph, panic: &'g Rule, entry: Option<&str>) -> Option<Self> {
+        let sinks: IndexSet<&FnItem> = panic.sinks().collect();
+        let may_panic = call_graph.reaching(sinks.iter().copied());
+        let nodes = match entry {
+            None => call_graph.fn_items().collect(),
+            Some(entry) => {
+                let entry = call_graph
+                    .fn_items()
+                    .find(|f| f.is_local() && (f.qualified_name() == entry || f.is(entry)))?;
+                let reachable = call_graph.reachable_from(std::slice::from_ref(entry));
+                let mut nodes: IndexSet<_> = reachable
+                    .into_iter()
+                    .filter(|f| may_panic.contains(f))
+                    .collect();
+                nodes.insert(entry);
+                nodes
+            }
+        };
+        Some(Graph {
+            call_graph,
+            nodes,
+            may_panic,
+            sinks,
+        })
+    }
+
+    pub fn write(&self, format: GraphFormat, path: &Path, src: &SourceCode, tcx: TyCtxt) {
+        let export = self.export(src, tcx);
+        let text = match format {
+            GraphFormat::Dot => self.dot(&export),
+            GraphFormat::Json => serde_json::to_string_pretty(&export).unwrap(),
+            GraphFormat::Graphml => graphml(&export),
+        };
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(path, text));
+        if let Err(err) = written {
+            eprintln!(
+                "warning: failed to write redpen graph `{}`: {err}",
+                path.display()
+            );
+        }
+    }
+
+    fn export(&self, src: &SourceCode, tcx: TyCtxt) -> Export {
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        for (idx, &f) in self.nodes.iter().enumerate() {
+            let body = f.body();
+            nodes.push(Node {
+                path: f.qualified_name(),
+                krate: f.def.krate().name,
+                span: src.origin(f.def.span()),
+                has_body: body.is_some(),
+                is_unsafe: f.def.fn_sig().value.safety == Safety::Unsafe,
+                may_panic: self.may_panic.contains(f),
+                summarized: self.call_graph.summary(f).is_some(),
+            });
+
+            let mut sites = match &body {
+                Some(body) => call_sites(tcx, body),
+                None => IndexMap::new(),
+            };
+            let site = |(span, kind)| Site {
+                location: src.origin(span),
+                kind,
+            };
+            for callee in self.call_graph.callees(f) {
+                let Some(callee_idx) = self.nodes.get_index_of(callee) else {
+                    continue;
+                };
+                let sites = sites.swap_remove(&callee.def).unwrap_or_default();
+                edges.push(Edge {
+                    caller: idx,
+                    callee: callee_idx,
+                    sites: sites.into_iter().map(site).collect(),
+                });
+            }
+            // The analysis doesn't follow drop glue, so `Drop` impls it runs are only edges
+            // to fns in the graph anyway.
+            for (callee, sites) in sites {
+                let Some(callee_idx) = self.nodes.get_index_of(&&FnItem::from(callee)) else {
+                    continue;
+                };
+                let sites: Vec<_> = sites
+                    .into_iter()
+                    .filter(|(_, kind)| *kind == CallKind::Drop)
+                    .map(site)
+                    .collect();
+                if !sites.is_empty() {
+                    edges.push(Edge {
+                        caller: idx,
+                        callee: callee_idx,
+                        sites,
+                    });
+                }
+            }
+        }
+        Export {
+            krate: rustc_public::local_crate().name,
+            nodes,
+            edges,
+        }
+    }
+
+    /// Nodes are red if they may panic, and filled if they are panic sinks. Local fns are
+    /// boxes, and upstream fns known from summaries only are dashed. Edges are labeled with
+    /// their first call site, and dashed unless it's a direct call.
+    fn dot(&self, export: &Export) -> String {
+        let mut dot = String::from("digraph {\n    node [fontname=monospace];\n");
+        for (idx, (f, node)) in self.nodes.iter().zip(&export.nodes).enumerate() {
+            let mut attrs = vec![format!("label={}", quote(&node.path))];
+            let mut styles = Vec::new();
+            if f.is_local() {
+                attrs.push("shape=box".to_owned());
+            }
+            if self.sinks.contains(f) {
+                styles.push("filled");
+                attrs.push("color=red fillcolor=red fontcolor=white".to_owned());
+            } else if node.may_panic {
+                attrs.push("color=red fontcolor=red".to_owned());
+            }
+            if node.summarized {
+                styles.push("dashed");
+            }
+            if !styles.is_empty() {
+                attrs.push(format!("style={}", quote(&styles.join(","))));
+            }
+            _ = writeln!(dot, "    n{idx} [{}];", attrs.join(" "));
+        }
+        for edge in &export.edges {
+            let mut attrs = Vec::new();
+            if let Some(site) = edge.sites.first() {
+                let loc = &site.location;
+                let mut label = format!("{}:{}:{}", loc.file, loc.line, loc.column);
+                if edge.sites.len() > 1 {
+                    label.push_str(&format!(" (+{})", edge.sites.len() - 1));
+                }
+                attrs.push(format!("label={}", quote(&label)));
+                if site.kind != CallKind::Direct {
+                    attrs.push("style=dashed".to_owned());
+                }
+            }
+            if export.nodes[edge.callee].may_panic {
+                attrs.push("color=red".to_owned());
+            }
+            _ = writeln!(
+                dot,
+                "    n{} -> n{} [{}];",
+                edge.caller,
+                edge.callee,
+                attrs.join(" ")
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// A quoted DOT string.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// GraphML with node and edge attributes as data. Call sites of an edge are joined like
+/// `src/lib.rs:4:9 direct; src/lib.rs:6:5 direct`.
+fn graphml(export: &Export) -> String {
+    const KEYS: &[(&str, &str, &str)] = &[
+        ("node", "path", "string"),
+        ("node", "crate", "string"),
+        ("node", "span", "string"),
+        ("node", "has_body", "boolean"),
+        ("node", "is_unsafe", "boolean"),
+        ("node", "may_panic", "boolean"),
+        ("node", "summarized", "boolean"),
+        ("edge", "kinds", "string"),
+        ("edge", "sites", "string"),
+    ];
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+    );
+    for (target, name, ty) in KEYS {
+        _ = writeln!(
+            xml,
+            "  <key id=\"{name}\" for=\"{target}\" attr.name=\"{name}\" attr.type=\"{ty}\"/>"
+        );
+    }
+    _ = writeln!(
+        xml,
+        "  <graph id=\"{}\" edgedefault=\"directed\">",
+        escape(&export.krate)
+    );
+    let data = |xml: &mut String, key: &str, value: &str| {
+        _ = writeln!(xml, "      <data key=\"{key}\">{}</data>", escape(value));
+    };
+    for (idx, node) in export.nodes.iter().enumerate() {
+        _ = writeln!(xml, "    <node id=\"n{idx}\">");
+        data(&mut xml, "path", &node.path);
+        data(&mut xml, "crate", &node.krate);
+        data(&mut xml, "span", &location(&node.span));
+        data(&mut xml, "has_body", &node.has_body.to_string());
+        data(&mut xml, "is_unsafe", &node.is_unsafe.to_string());
+        data(&mut xml, "may_panic", &node.may_panic.to_string());
+        data(&mut xml, "summarized", &node.summarized.to_string());
+        xml.push_str("    </node>\n");
+    }
+    for edge in &export.edges {
+        _ = writeln!(
+            xml,
+            "    <edge source=\"n{}\" target=\"n{}\">",
+            edge.caller, edge.callee
+        );
+        let mut kinds: Vec<_> = edge.sites.iter().map(|s| s.kind.name()).collect();
+        kinds.sort();
+        kinds.dedup();
+        let sites: Vec<_> = edge
+            .sites
+            .iter()
+            .map(|s| format!("{} {}", location(&s.location), s.kind.name()))
+            .collect();
+        data(&mut xml, "kinds", &kinds.join(","));
+        data(&mut xml, "sites", &sites.join("; "));
+        xml.push_str("    </edge>\n");
+    }
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+fn location(loc: &Location) -> String {
+    format!("{}:{}:{}", loc.file, loc.line, loc.column)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl CallKind {
+    fn name(self) -> &'static str {
+        match self {
+            CallKind::Direct => "direct",
+            CallKind::Trait => "trait",
+            CallKind::Dyn => "dyn",
+            CallKind::FnPtr => "fnptr",
+            CallKind::Drop => "drop",
+        }
+    }
+
+    fn of_call(fn_def: FnDef, args: &GenericArgs, tcx: TyCtxt) -> Self {
+        let def_id = internal(tcx, fn_def.def_id());
+        if tcx.lang_items().drop_in_place_fn() == Some(def_id) {
+            return CallKind::Drop;
+        }
+        if tcx.trait_of_assoc(def_id).is_none() {
+            return CallKind::Direct;
+        }
+        // The first generic arg of a trait method is the `Self` type.
+        match args.0.first() {
+            Some(GenericArgKind::Type(ty))
+                if matches!(ty.kind(), TyKind::RigidTy(RigidTy::Dynamic(..))) =>
+            {
+                CallKind::Dyn
+            }
+            _ => CallKind::Trait,
+        }
+    }
+}
+
+/// Spots in the body using each fn item, with how it's used.
+fn call_sites(tcx: TyCtxt, body: &Body) -> IndexMap<FnDef, Vec<(Span, CallKind)>> {
+    let mut visitor = CallSites {
+        tcx,
+        body,
+        sites: IndexMap::new(),
+    };
+    visitor.visit_body(body);
+    visitor.sites
+}
+
+struct CallSites<'tcx, 'a> {
+    tcx: TyCtxt<'tcx>,
+    body: &'a Body,
+    sites: IndexMap<FnDef, Vec<(Span, CallKind)>>,
+}
+
+impl CallSites<'_, '_> {
+    fn add(&mut self, fn_def: FnDef, span: Span, kind: CallKind) {
+        let span = local_span(self.tcx, self.body.span, span).unwrap_or(span);
+        self.sites.entry(fn_def).or_default().push((span, kind));
+    }
+}
+
+impl MirVisitor for CallSites<'_, '_> {
+    fn visit_terminator(&mut self, term: &Terminator, location: MirLoc) {
+        if let TerminatorKind::Call { func, args, .. } = &term.kind {
+            if let Ok(ty) = func.ty(self.body.locals())
+                && let Some((fn_def, generic_args)) = ty.kind().fn_def()
+            {
+                let kind = CallKind::of_call(fn_def, generic_args, self.tcx);
+                self.add(fn_def, location.span(), kind);
+            }
+            // Fn items passed as arguments are visited as operands.
+            for arg in args {
+                self.visit_operand(arg, location);
+            }
+            return;
+        }
+        if let TerminatorKind::Drop { place, .. } = &term.kind
+            && let Ok(ty) = place.ty(self.body.locals())
+        {
+            for fn_def in drop_fns(self.tcx, ty) {
+                self.add(fn_def, location.span(), CallKind::Drop);
+            }
+        }
+        self.super_terminator(term, location);
+    }
+
+    fn visit_operand(&mut self, operand: &Operand, location: MirLoc) {
+        if let Ok(ty) = operand.ty(self.body.locals())
+            && let Some((fn_def, _)) = ty.kind().fn_def()
+        {
+            self.add(fn_def, location.span(), CallKind::FnPtr);
+        }
+        self.super_operand(operand, location);
+    }
+}
+
+/// `Drop::drop` impls run by the drop glue of a type: of the type itself, its fields, and
+/// the pointee of a `Box`. Elements dropped by collections through `drop_in_place` on a
+/// generic type, and trait objects, are left out.
+fn drop_fns(tcx: TyCtxt, ty: Ty) -> impl Iterator<Item = FnDef> {
+    let mut dtors = IndexSet::new();
+    collect_drops(tcx, internal(tcx, ty), &mut dtors, &mut HashSet::new());
+    dtors.into_iter().filter_map(move |did| {
+        let args = rustc_ty::GenericArgs::identity_for_item(tcx, did);
+        let fn_ty: Ty = stable(rustc_ty::Ty::new_fn_def(tcx, did, args));
+        fn_ty.kind().fn_def().map(|(fn_def, _)| fn_def)
+    })
+}
+
+fn collect_drops<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ty: rustc_ty::Ty<'tcx>,
+    dtors: &mut IndexSet<DefId>,
+    seen: &mut HashSet<rustc_ty::Ty<'tcx>>,
+) {
+    if !seen.insert(ty) {
+        return;
+    }
+    match ty.kind() {
+        rustc_ty::Adt(adt, args) => {
+            if adt.is_manually_drop() {
+                return;
+            }
+            if let Some(dtor) = adt.destructor(tcx) {
+                dtors.insert(dtor.did);
+            }
+            if ty.is_box() {
+                collect_drops(tcx, ty.expect_boxed_ty(), dtors, seen);
+            }
+            for field in adt.all_fields() {
+                collect_drops(tcx, field.ty(tcx, args), dtors, seen);
+            }
+        }
+        rustc_ty::Tuple(tys) => {
+            for ty in tys.iter() {
+                collect_drops(tcx, ty, dtors, seen);
+            }
+        }
+        rustc_ty::Array(ty, _) | rustc_ty::Slice(ty) => collect_drops(tcx, *ty, dtors, seen),
+        rustc_ty::Closure(_, args) => {
+            for ty in args.as_closure().upvar_tys() {
+                collect_drops(tcx, ty, dtors, seen);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(path: &str, line: usize) -> Node {
+        Node {
+            path: path.to_owned(),
+            krate: "app".to_owned(),
+            span: Location {
+                file: "src/main.rs".to_owned(),
+                line,
+                column: 1,
+            },
+            has_body: true,
+            is_unsafe: false,
+            may_panic: true,
+            summarized: false,
+        }
+    }
+
+    fn site(line: usize, kind: CallKind) -> Site {
+        Site {
+            location: Location {
+                file: "src/main.rs".to_owned(),
+                line,
+                column: 5,
+            },
+            kind,
+        }
+    }
+
+    #[test]
+    fn quote_dot_strings() {
+        assert_eq!(quote("app::main"), r#""app::main""#);
+        assert_eq!(quote(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote(r"C:\src"), r#""C:\\src""#);
+    }
+
+    #[test]
+    fn escape_xml() {
+        assert_eq!(
+            escape(r#"<Vec<T> as Drop>::drop & "x""#),
+            "&lt;Vec&lt;T&gt; as Drop&gt;::drop &amp; &quot;x&quot;"
+        );
+    }
+
+    #[test]
+    fn graphml_nodes_and_edges() {
+        let export = Export {
+            krate: "app".to_owned(),
+            nodes: vec![node("app::main", 1), node("<T as Drop>::drop", 9)],
+            edges: vec![Edge {
+                caller: 0,
+                callee: 1,
+                sites: vec![site(4, CallKind::Drop), site(2, CallKind::Direct)],
+            }],
+        };
+        let xml = graphml(&export);
+        assert!(xml.starts_with("<?xml"), "{xml}");
+        assert!(
+            xml.contains(r#"<graph id="app" edgedefault="directed">"#),
+            "{xml}"
+        );
+        assert!(
+            xml.contains(
+                r#"<key id="may_panic" for="node" attr.name="may_panic" attr.type="boolean"/>"#
+            ),
+            "{xml}"
+        );
+        assert!(
+            xml.contains(r#"<data key="path">&lt;T as Drop&gt;::drop</data>"#),
+            "{xml}"
+        );
+        assert!(
+            xml.contains(r#"<data key="span">src/main.rs:9:1</data>"#),
+            "{xml}"
+        );
+        assert!(xml.contains(r#"<edge source="n0" target="n1">"#), "{xml}");
+        assert!(
+            xml.contains(r#"<data key="kinds">direct,drop</data>"#),
+            "{xml}"
+        );
+        assert!(
+            xml.contains(
+                r#"<data key="sites">src/main.rs:4:5 drop; src/main.rs:2:5 direct</data>"#
+            ),
+            "{xml}"
+        );
+        assert!(xml.ends_with("</graphml>\n"), "{xml}");
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    call_graph::{CallGraph, call_sites},
+    config::Config,
+    detect::{PANIC, Rule, fn_matches},
+    diagnostics::{Emitter, SourceCode},
+    fn_item::FnItem,
+    locks::lock_acquires,
+    summary::summarized_sinks,
+};
+use indexmap::IndexSet;
+use redpen::{
+    options::Check,
+    report::{Finding, Level},
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{CrateDef, ty::Span};
+
+/// Tag for interrupt or signal handlers.
+const ISR: &str = "isr";
+
+/// Allocation primitives, in the form of [`crate::config::Forbid::path`].
+const ALLOCATIONS: &[&str] = &[
+    "alloc::alloc::__rust_alloc*",
+    "alloc::alloc::__rust_realloc",
+    "alloc::alloc::exchange_malloc",
+    "*::Allocator::allocate*",
+    "*::Allocator::grow*",
+    "*::GlobalAlloc::alloc*",
+    "*::GlobalAlloc::realloc",
+];
+
+/// Handlers tagged with `#[redpen::isr]`, each with properties it violates.
+pub struct Isr {
+    handlers: Vec<Handler>,
+}
+
+struct Handler {
+    fn_item: FnItem,
+    body: Span,
+    violations: Vec<Violation>,
+}
+
+/// A property broken by a handler, with the shortest witness call path.
+struct Violation {
+    /// What the handler may do, like "allocate".
+    property: String,
+    path: Vec<FnItem>,
+    /// Calls in the handler body to the next fn on the path.
+    calls: IndexSet<Span>,
+}
+
+impl Isr {
+    pub fn new(call_graph: &CallGraph, fn_items: &[FnItem], config: &Config, tcx: TyCtxt) -> Self {
+        let sets = sink_sets(call_graph, config, tcx);
+        let sinks: Vec<(&String, IndexSet<&FnItem>)> = sets
+            .iter()
+            .map(|(property, fns)| (property, fns.iter().collect()))
+            .collect();
+
+        let mut handlers = Vec::new();
+        for fn_item in fn_items.iter().filter(|f| f.has_redpen_attr(ISR)) {
+            let Some(body) = fn_item.body() else { continue };
+            let violations: Vec<_> = sinks
+                .iter()
+                .filter_map(|(property, sinks)| {
+                    let path = call_graph.shortest_path(fn_item, sinks)?;
+                    let next = path.get(1).map(|f| f.def);
+                    let calls = call_sites(tcx, &body, &next.into_iter().collect());
+                    Some(Violation {
+                        property: property.to_string(),
+                        path,
+                        calls,
+                    })
+                })
+                .collect();
+            if !violations.is_empty() {
+                handlers.push(Handler {
+                    fn_item: fn_item.clone(),
+                    body: body.span,
+                    violations,
+                });
+            }
+        }
+        Isr { handlers }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    pub fn emit(&self, src: &SourceCode, out: &Emitter) {
+        for handler in &self.handlers {
+            handler.emit(src, out);
+        }
+    }
+}
+
+/// Properties that handlers must not break.
+pub fn properties(config: &Config) -> Vec<String> {
+    let mut properties = vec![
+        PANIC.to_owned(),
+        "allocate".to_owned(),
+        "acquire a lock".to_owned(),
+    ];
+    properties.extend(
+        config
+            .isr
+            .non_reentrant
+            .iter()
+            .map(|pattern| format!("call non-reentrant `{pattern}`")),
+    );
+    properties
+}
+
+/// Each of [`properties`] with fns breaking it directly, and upstream fns summarized to
+/// break it.
+pub fn sink_sets(
+    call_graph: &CallGraph,
+    config: &Config,
+    tcx: TyCtxt,
+) -> Vec<(String, Vec<FnItem>)> {
+    let matching = |patterns: &[&str]| -> Vec<FnItem> {
+        call_graph
+            .fn_items()
+            .filter(|f| patterns.iter().any(|p| fn_matches(p, f)))
+            .cloned()
+            .collect()
+    };
+
+    let mut sinks = vec![
+        Rule::panic(call_graph, tcx).sinks().cloned().collect(),
+        matching(ALLOCATIONS),
+        lock_acquires(call_graph),
+    ];
+    sinks.extend(
+        config
+            .isr
+            .non_reentrant
+            .iter()
+            .map(|pattern| matching(&[pattern])),
+    );
+    properties(config)
+        .into_iter()
+        .zip(sinks)
+        .map(|(property, mut sinks)| {
+            for f in summarized_sinks(call_graph, &property) {
+                if !sinks.contains(&f) {
+                    sinks.push(f);
+                }
+            }
+            (property, sinks)
+        })
+        .collect()
+}
+
+impl Handler {
+    fn emit(&self, src: &SourceCode, out: &Emitter) {
+        let annots = self.violations.iter().flat_map(|v| {
+            v.calls.iter().map(|sp| {
+                (
+                    src.offset(self.body, *sp),
+                    format!("This may {}.", v.property),
+                )
+            })
+        });
+        let notes = self.violations.iter().map(|v| {
+            let path: Vec<_> = v.path.iter().map(|f| format!("`{}`", f.name)).collect();
+            format!("May {} through {}", v.property, path.join(" -> "))
+        });
+
+        let title = format!(
+            "Interrupt handler `{}` is not safe to run in interrupt context.",
+            self.fn_item.name
+        );
+        let finding = Finding::new(
+            Check::Isr,
+            Level::Error,
+            title,
+            self.fn_item.qualified_name(),
+        )
+        .about(self.violations.iter().map(|v| v.property.clone()))
+        .witnesses(
+            self.violations
+                .iter()
+                .map(|v| v.path.iter().map(|f| f.qualified_name()).collect()),
+        )
+        .snippet(
+            src.snippet(self.body, self.fn_item.def.span(), "For this handler.")
+                .primaries(annots),
+        )
+        .notes(notes);
+        out.emit(finding);
+    }
+}
@@ -0,0 +1,491 @@
+use crate::{
+    call_graph::{CallGraph, contains_span},
+    detect::path_matches,
+    diagnostics::{Emitter, SourceCode},
+    fn_item::FnItem,
+};
+use indexmap::{IndexMap, IndexSet};
+use redpen::{
+    options::Check,
+    report::{Finding, Level, Snippet},
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{
+    CrateDef,
+    mir::{Body, Operand, TerminatorKind},
+    ty::{GenericArgs, RigidTy, Span, Ty, TyKind},
+};
+use std::rc::Rc;
+
+/// Lock acquisitions, identified by fn paths without generic arguments. Items of `core`
+/// are named by their `std` path, unless the crate is `no_std`.
+const ACQUIRES: &[(&str, Acquire)] = &[
+    ("std::sync::Mutex::lock", Acquire::Lock),
+    ("std::sync::RwLock::read", Acquire::Read),
+    ("std::sync::RwLock::write", Acquire::Write),
+    ("std::cell::RefCell::borrow", Acquire::Borrow),
+    ("std::cell::RefCell::borrow_mut", Acquire::BorrowMut),
+    ("core::cell::RefCell::borrow", Acquire::Borrow),
+    ("core::cell::RefCell::borrow_mut", Acquire::BorrowMut),
+];
+
+/// Guards that release their lock when dropped.
+const GUARDS: &[(&str, &str)] = &[
+    ("std::sync::MutexGuard", "Mutex"),
+    ("std::sync::RwLockReadGuard", "RwLock"),
+    ("std::sync::RwLockWriteGuard", "RwLock"),
+    ("std::cell::Ref", "RefCell"),
+    ("std::cell::RefMut", "RefCell"),
+    ("core::cell::Ref", "RefCell"),
+    ("core::cell::RefMut", "RefCell"),
+];
+
+const MEM_DROPS: &[&str] = &["std::mem::drop", "core::mem::drop"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Acquire {
+    Lock,
+    Read,
+    Write,
+    Borrow,
+    BorrowMut,
+}
+
+impl Acquire {
+    fn lock_name(self) -> &'static str {
+        match self {
+            Acquire::Lock => "Mutex",
+            Acquire::Read | Acquire::Write => "RwLock",
+            Acquire::Borrow | Acquire::BorrowMut => "RefCell",
+        }
+    }
+
+    fn is_refcell(self) -> bool {
+        matches!(self, Acquire::Borrow | Acquire::BorrowMut)
+    }
+}
+
+/// Fns acquiring a lock or borrowing a RefCell.
+pub fn lock_acquires(call_graph: &CallGraph) -> Vec<FnItem> {
+    call_graph
+        .fn_items()
+        .filter(|f| ACQUIRES.iter().any(|(path, _)| path_matches(path, &f.name)))
+        .cloned()
+        .collect()
+}
+
+/// A lock identified by its type, like `Mutex<Vec<u8>>`.
+///
+/// Different instances of the same lock type are not distinguished.
+type LockClass = String;
+
+fn lock_class(lock_name: &str, args: &GenericArgs) -> Option<LockClass> {
+    let ty = args.0.iter().find_map(|arg| arg.ty())?;
+    Some(format!("{lock_name}<{ty}>"))
+}
+
+/// A span in a local fn body.
+#[derive(Debug, Clone)]
+struct Site {
+    fn_item: FnItem,
+    body: Span,
+    span: Span,
+}
+
+#[derive(Debug)]
+enum Event {
+    Acquire {
+        class: LockClass,
+        kind: Acquire,
+        span: Span,
+    },
+    Release {
+        class: LockClass,
+    },
+    Call {
+        callee: FnItem,
+        span: Span,
+    },
+}
+
+/// Events in a fn body, along with the body span.
+type Events = (Span, Vec<Event>);
+
+#[derive(Debug, Clone)]
+struct Held {
+    class: LockClass,
+    kind: Acquire,
+    site: Site,
+}
+
+/// Acquiring a lock of `class` while `held` is held, reached through `path`.
+#[derive(Debug, Clone)]
+struct Witness {
+    held: Held,
+    class: LockClass,
+    kind: Acquire,
+    site: Site,
+    path: Vec<FnItem>,
+}
+
+/// Lock order inversions and RefCell re-borrows found along call paths.
+#[derive(Debug, Default)]
+pub struct Locks {
+    /// Lock order edges: (held, acquired) => first witness.
+    orders: IndexMap<(LockClass, LockClass), Witness>,
+    reborrows: IndexMap<(FnItem, Span), Witness>,
+}
+
+impl Locks {
+    pub fn new(call_graph: &CallGraph, entries: &[FnItem], tcx: TyCtxt) -> Self {
+        let acquires = lock_acquires(call_graph);
+        let mut walk = Walk {
+            tcx,
+            may_acquire: call_graph.reaching(&acquires),
+            events: IndexMap::new(),
+            walked: IndexMap::new(),
+            locks: Locks::default(),
+        };
+        for entry in entries {
+            if walk.may_acquire.contains(entry) {
+                walk.walk(entry);
+            }
+        }
+        walk.locks
+    }
+
+    fn inversions(&self) -> impl Iterator<Item = (&Witness, &Witness)> {
+        self.orders
+            .iter()
+            .filter_map(|((held, acquired), witness)| {
+                // Report each pair once.
+                (held < acquired)
+                    .then(|| self.orders.get(&(acquired.clone(), held.clone())))
+                    .flatten()
+                    .map(|reversed| (witness, reversed))
+            })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reborrows.is_empty() && self.inversions().next().is_none()
+    }
+
+    pub fn emit(&self, src: &SourceCode, out: &Emitter) {
+        for (w1, w2) in self.inversions() {
+            let finding = Finding::new(
+                Check::Locks,
+                Level::Error,
+                "Locks may be acquired in inconsistent orders.",
+                w1.site.fn_item.qualified_name(),
+            )
+            .about([w1, w2].map(|w| w.class.to_string()))
+            .snippets([w1, w2].map(|w| {
+                let label = format!("Acquires `{}` while holding `{}`.", w.class, w.held.class);
+                w.snippet(src, label)
+            }))
+            .notes([w1, w2].map(|w| w.call_path()));
+            out.emit(finding);
+        }
+        for w in self.reborrows.values() {
+            let (title, label) = if w.kind == Acquire::BorrowMut {
+                (
+                    "A RefCell may be mutably borrowed while already borrowed.",
+                    format!("Mutably borrows `{}` while it's borrowed.", w.class),
+                )
+            } else {
+                (
+                    "A RefCell may be borrowed while already mutably borrowed.",
+                    format!("Borrows `{}` while it's mutably borrowed.", w.class),
+                )
+            };
+            let finding = Finding::new(
+                Check::Locks,
+                Level::Error,
+                title,
+                w.site.fn_item.qualified_name(),
+            )
+            .about([w.class.to_string()])
+            .snippet(w.snippet(src, label))
+            .note(w.call_path());
+            out.emit(finding);
+        }
+    }
+}
+
+impl Witness {
+    fn snippet(&self, src: &SourceCode, label: String) -> Snippet {
+        let site = &self.site;
+        let snippet = src
+            .snippet(site.body, site.fn_item.def.span(), "For this function.")
+            .primary(src.offset(site.body, site.span), label);
+        if self.held.site.fn_item == site.fn_item && self.held.site.span != site.span {
+            let held = &self.held.site;
+            snippet.context(
+                src.offset(held.body, held.span),
+                format!("`{}` is acquired here.", self.held.class),
+            )
+        } else {
+            snippet
+        }
+    }
+
+    fn call_path(&self) -> String {
+        let path: Vec<_> = self.path.iter().map(|f| format!("`{}`", f.name)).collect();
+        format!("Call path: {}", path.join(" -> "))
+    }
+}
+
+struct Walk<'tcx, 'g> {
+    tcx: TyCtxt<'tcx>,
+    may_acquire: IndexSet<&'g FnItem>,
+    events: IndexMap<FnItem, Option<Rc<Events>>>,
+    /// Locks held by callers and released by the walked fn, by the walk key.
+    walked: IndexMap<WalkKey, Vec<LockClass>>,
+    locks: Locks,
+}
+
+/// A fn item, the call site shown for its events if it's upstream, and the classes and
+/// kinds of locks held when it's called. Walking the same key again finds nothing new.
+type WalkKey = (FnItem, Option<(FnItem, Span)>, Vec<(LockClass, Acquire)>);
+
+/// A fn item on the walk stack, with its events left to walk.
+struct Frame {
+    fn_item: FnItem,
+    events: Rc<Events>,
+    next: usize,
+    /// The call site in the innermost local caller, used to show events in upstream fns.
+    site: Option<Site>,
+    key: WalkKey,
+    /// Number of locks held by callers and still held.
+    held_len: usize,
+    /// Locks held by callers and released here.
+    released: Vec<LockClass>,
+}
+
+impl Frame {
+    fn local_site(&self, span: Span) -> Site {
+        match &self.site {
+            Some(site) if !self.fn_item.is_local() => site.clone(),
+            _ => Site {
+                fn_item: self.fn_item.clone(),
+                body: self.events.0,
+                span,
+            },
+        }
+    }
+}
+
+impl Walk<'_, '_> {
+    /// Walk events of fn items from the entry in basic block order. A lock is held from its
+    /// acquisition until its guard is dropped, or until the fn returns.
+    ///
+    /// Calls are followed with an explicit stack, since deep call chains would overflow the
+    /// native stack, and a fn called again with the same locks held is not walked again.
+    fn walk(&mut self, entry: &FnItem) {
+        let mut held = Vec::new();
+        let mut frames = Vec::new();
+        let mut path = Vec::new();
+        self.call(entry, None, &mut held, &mut frames, &mut path);
+
+        while let Some(frame) = frames.last_mut() {
+            let events = frame.events.clone();
+            let Some(event) = events.1.get(frame.next) else {
+                let Some(frame) = frames.pop() else { break };
+                // Guards still alive in the callee are dropped on return, as far as we can
+                // tell.
+                held.truncate(frame.held_len);
+                path.pop();
+                self.walked.insert(frame.key, frame.released);
+                continue;
+            };
+            frame.next += 1;
+
+            match event {
+                Event::Acquire { class, kind, span } => {
+                    let site = frame.local_site(*span);
+                    self.acquire(&mut held, class, *kind, site, &path);
+                }
+                Event::Release { class } => release(&mut held, &mut frames, class),
+                Event::Call { callee, span } => {
+                    // Stop at recursion.
+                    if path.contains(callee) {
+                        continue;
+                    }
+                    let site = frame.local_site(*span);
+                    self.call(callee, Some(site), &mut held, &mut frames, &mut path);
+                }
+            }
+        }
+    }
+
+    /// Push a frame to walk the fn item, unless it has been walked with the same locks held,
+    /// in which case only the locks it released are released again.
+    fn call(
+        &mut self,
+        fn_item: &FnItem,
+        site: Option<Site>,
+        held: &mut Vec<Held>,
+        frames: &mut Vec<Frame>,
+        path: &mut Vec<FnItem>,
+    ) {
+        let key = (
+            fn_item.clone(),
+            site.as_ref()
+                .filter(|_| !fn_item.is_local())
+                .map(|site| (site.fn_item.clone(), site.span)),
+            held.iter().map(|h| (h.class.clone(), h.kind)).collect(),
+        );
+        if let Some(released) = self.walked.get(&key) {
+            for class in released {
+                release(held, frames, class);
+            }
+            return;
+        }
+        let Some(events) = self.events(fn_item) else {
+            return;
+        };
+        path.push(fn_item.clone());
+        frames.push(Frame {
+            fn_item: fn_item.clone(),
+            events,
+            next: 0,
+            site,
+            key,
+            held_len: held.len(),
+            released: Vec::new(),
+        });
+    }
+
+    fn acquire(
+        &mut self,
+        held: &mut Vec<Held>,
+        class: &LockClass,
+        kind: Acquire,
+        site: Site,
+        path: &[FnItem],
+    ) {
+        for h in held.iter() {
+            let witness = || Witness {
+                held: h.clone(),
+                class: class.clone(),
+                kind,
+                site: site.clone(),
+                path: path.to_vec(),
+            };
+            if h.class != *class {
+                self.locks
+                    .orders
+                    .entry((h.class.clone(), class.clone()))
+                    .or_insert_with(witness);
+            } else if kind.is_refcell()
+                && (kind == Acquire::BorrowMut || h.kind == Acquire::BorrowMut)
+            {
+                self.locks
+                    .reborrows
+                    .entry((site.fn_item.clone(), site.span))
+                    .or_insert_with(witness);
+            }
+        }
+        held.push(Held {
+            class: class.clone(),
+            kind,
+            site,
+        });
+    }
+
+    fn events(&mut self, fn_item: &FnItem) -> Option<Rc<Events>> {
+        if let Some(events) = self.events.get(fn_item) {
+            return events.clone();
+        }
+        let events = fn_item
+            .body()
+            .map(|body| Rc::new((body.span, self.collect_events(&body))));
+        self.events.insert(fn_item.clone(), events.clone());
+        events
+    }
+
+    fn collect_events(&self, body: &Body) -> Vec<Event> {
+        let locals = body.locals();
+        let span_in_body = |span: Span| {
+            if contains_span(self.tcx, body.span, span) {
+                span
+            } else {
+                body.span
+            }
+        };
+
+        let mut events = Vec::new();
+        for block in &body.blocks {
+            let term = &block.terminator;
+            match &term.kind {
+                TerminatorKind::Call { func, args, .. } => {
+                    let Ok(ty) = func.ty(locals) else { continue };
+                    let TyKind::RigidTy(RigidTy::FnDef(fn_def, generic_args)) = ty.kind() else {
+                        continue;
+                    };
+                    let name = fn_def.name();
+                    let span = span_in_body(term.span);
+                    if let Some((_, kind)) =
+                        ACQUIRES.iter().find(|(path, _)| path_matches(path, &name))
+                    {
+                        if let Some(class) = lock_class(kind.lock_name(), &generic_args) {
+                            events.push(Event::Acquire {
+                                class,
+                                kind: *kind,
+                                span,
+                            });
+                        }
+                    } else if MEM_DROPS.iter().any(|path| path_matches(path, &name)) {
+                        let released = args.first().and_then(|arg| match arg {
+                            Operand::Copy(place) | Operand::Move(place) => place.ty(locals).ok(),
+                            Operand::Constant(_) => None,
+                        });
+                        events.extend(
+                            released
+                                .and_then(guard_class)
+                                .map(|class| Event::Release { class }),
+                        );
+                    } else {
+                        let callee = FnItem::from(fn_def);
+                        // The internal locking of the sysroot is not ours to report.
+                        if self.may_acquire.contains(&callee) && !callee.is_sysroot() {
+                            events.push(Event::Call { callee, span });
+                        }
+                    }
+                }
+                TerminatorKind::Drop { place, .. } => {
+                    if let Some(class) = place.ty(locals).ok().and_then(guard_class) {
+                        events.push(Event::Release { class });
+                    }
+                }
+                _ => (),
+            }
+        }
+        events
+    }
+}
+
+/// Release the last held lock of the class, which may be held by callers of the fns
+/// being walked.
+fn release(held: &mut Vec<Held>, frames: &mut [Frame], class: &LockClass) {
+    let Some(pos) = held.iter().rposition(|h| h.class == *class) else {
+        return;
+    };
+    held.remove(pos);
+    for frame in frames.iter_mut().filter(|frame| frame.held_len > pos) {
+        frame.held_len -= 1;
+        frame.released.push(class.clone());
+    }
+}
+
+/// The lock class released by dropping a guard of this type.
+fn guard_class(ty: Ty) -> Option<LockClass> {
+    let TyKind::RigidTy(RigidTy::Adt(adt, args)) = ty.kind() else {
+        return None;
+    };
+    let name = adt.name();
+    let (_, lock_name) = GUARDS
+        .iter()
+        .find(|(guard, _)| path_matches(guard, &name))?;
+    lock_class(lock_name, &args)
+}
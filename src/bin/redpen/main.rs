@@ -1,56 +1,296 @@
 #![feature(rustc_private)]
 
 extern crate indexmap;
+extern crate rustc_ast;
 extern crate rustc_driver;
+extern crate rustc_hir;
 extern crate rustc_interface;
 extern crate rustc_middle;
 extern crate rustc_public;
+extern crate rustc_span;
 
+mod cache;
 mod call_graph;
+mod config;
+mod crate_attrs;
 mod detect;
 mod diagnostics;
+mod entries;
 mod fn_item;
+mod graph;
+mod isr;
+mod locks;
+mod panics_doc;
+mod recursion;
+mod stack_depth;
+mod summary;
+mod tests;
 
-use crate::{call_graph::CallGraph, detect::Detect, diagnostics::SourceCode, fn_item::FnItem};
+use crate::{
+    cache::{Cache, write_json},
+    call_graph::CallGraph,
+    config::Config,
+    detect::{Detect, Rule},
+    diagnostics::{Emitter, SourceCode},
+    entries::EntryFilter,
+    fn_item::FnItem,
+    graph::Graph,
+    isr::Isr,
+    locks::Locks,
+    panics_doc::PanicsDoc,
+    recursion::Recursion,
+    stack_depth::StackDepth,
+    summary::Summaries,
+};
+use redpen::{
+    baseline::Baseline,
+    options::{Check, ENV_OPTIONS, Format, Options},
+    report::CrateReport,
+};
+use rustc_ast::ast;
+use rustc_driver::{Callbacks, Compilation};
+use rustc_interface::interface::{self, Compiler};
 use rustc_middle::ty::TyCtxt;
-use rustc_public::CrateDef;
-use std::ops::ControlFlow;
+use rustc_span::Symbol;
 
 fn main() {
-    let rustc_args: Vec<_> = std::env::args().collect();
-    _ = rustc_public::run_with_tcx!(&rustc_args, analysis);
+    let mut rustc_args: Vec<_> = std::env::args().collect();
+    let opts = Options::take_from(&mut rustc_args);
+    if !opts.summary_only {
+        crate_attrs::inject_flags(&mut rustc_args);
+    }
+    std::process::exit(rustc_driver::catch_with_exit_code(|| {
+        rustc_driver::run_compiler(&rustc_args, &mut Redpen { opts })
+    }));
+}
+
+struct Redpen {
+    opts: Options,
+}
+
+impl Callbacks for Redpen {
+    fn config(&mut self, config: &mut interface::Config) {
+        // Record the options and the baseline in dep-info, so that cargo reruns redpen on a
+        // fresh crate when they change.
+        let baseline = self.opts.baseline.clone();
+        config.psess_created = Some(Box::new(move |psess| {
+            let json = std::env::var(ENV_OPTIONS).ok();
+            psess.env_depinfo.get_mut().insert((
+                Symbol::intern(ENV_OPTIONS),
+                json.as_deref().map(Symbol::intern),
+            ));
+            if let Some(path) = baseline {
+                psess
+                    .file_depinfo
+                    .get_mut()
+                    .insert(Symbol::intern(&path.to_string_lossy()));
+            }
+        }));
+    }
+
+    fn after_crate_root_parsing(&mut self, _: &Compiler, krate: &mut ast::Crate) -> Compilation {
+        crate_attrs::dedup(krate);
+        Compilation::Continue
+    }
+
+    fn after_analysis(&mut self, _: &Compiler, tcx: TyCtxt) -> Compilation {
+        _ = rustc_public::rustc_internal::run(tcx, || {
+            analysis(tcx, &self.opts);
+            if self.opts.format == Format::Human {
+                for f in fn_item::skipped_bodies() {
+                    eprintln!(
+                        "warning: redpen skips the body of `{}`, which holds constants of \
+                         generic layout",
+                        f.name
+                    );
+                }
+            }
+        });
+        Compilation::Continue
+    }
 }
 
-fn analysis(tcx: TyCtxt) -> ControlFlow<(), ()> {
+fn analysis(tcx: TyCtxt, opts: &Options) {
+    let mut fn_items = Vec::new();
     let mut entries = Vec::new();
+    let mut exported = Vec::new();
     let mut call_graph = CallGraph::default();
     let local_crate = rustc_public::local_crate();
+    let config = Config::load(opts.config.as_deref());
+    let sink_keys = summary::sink_keys(&config, opts);
+    let report_file = opts
+        .report_dir
+        .as_ref()
+        .map(|dir| dir.join(format!("{}.txt", report_name(tcx, &local_crate.name))));
+
+    // Skip the analysis of an unchanged crate, replaying its findings if any, unless the
+    // call graph is exported.
+    let summarized = opts
+        .summary_dir
+        .as_deref()
+        .is_none_or(|dir| summary::is_written(tcx, dir, &sink_keys));
+    if opts.summary_only && summarized {
+        return;
+    }
+    let cache = opts
+        .cache_dir
+        .as_deref()
+        .filter(|_| !opts.summary_only)
+        .map(|dir| Cache::new(dir, tcx, &config, opts));
+    let baseline = load_baseline(opts);
+    if summarized
+        && opts.graph.is_none()
+        && let Some(cached) = cache.as_ref().and_then(Cache::load)
+    {
+        let out = Emitter::new(opts.format, report_file, baseline);
+        for finding in cached.findings {
+            out.emit(finding);
+        }
+        let report = CrateReport {
+            findings: out.finish(),
+            ..cached
+        };
+        write_results(tcx, opts, &report);
+        return;
+    }
+
+    let summaries = Summaries::load(tcx, opts.summary_dir.as_deref(), &sink_keys);
+    let entry_filter = EntryFilter::new(tcx, opts);
 
     for f in local_crate.fn_defs() {
         let fn_item = FnItem::new(f);
-        call_graph.reach_in_depth(fn_item.clone());
+        call_graph.reach_in_depth(fn_item.clone(), &summaries);
 
-        // When a top-level function is tagged, don't treat it as an entry item to report.
-        let mut push_entry = true;
-        for attr in f.all_tool_attrs() {
-            if attr.as_str().trim() == "#[redpen::silence_panic]" {
-                push_entry = false;
+        if entry_filter.is_entry(&fn_item) {
+            if entry_filter.is_exported(&fn_item) {
+                exported.push(fn_item.clone());
             }
+            entries.push(fn_item.clone());
         }
-        if push_entry {
-            entries.push(fn_item);
-        }
+        fn_items.push(fn_item);
     }
 
     call_graph.sort();
 
-    let detect = Detect::new(&call_graph, entries);
-    let spots = call_graph.analyze(&detect, tcx);
+    let detect = Detect::new(&call_graph, entries, exported, &config, opts, tcx);
+    if let Some(dir) = &opts.summary_dir {
+        let sink_sets = summary::sink_sets(&call_graph, &detect, &config, opts, tcx);
+        summary::write(tcx, dir, &call_graph, &fn_items, &sink_sets);
+    }
+    if opts.summary_only {
+        return;
+    }
+    let mut graph_path = None;
+    if let Some(format) = opts.graph {
+        let panic = Rule::panic(&call_graph, tcx);
+        if let Some(graph) = Graph::new(&call_graph, &panic, opts.graph_entry.as_deref()) {
+            let name = format!(
+                "{}.{}",
+                report_name(tcx, &local_crate.name),
+                format.extension()
+            );
+            let path = opts.graph_dir.clone().unwrap_or_default().join(name);
+            graph.write(format, &path, &SourceCode::new(tcx), tcx);
+            graph_path = Some(path);
+        } else if opts.results_dir.is_none()
+            && let Some(entry) = &opts.graph_entry
+        {
+            // cargo-redpen warns once if no crate of the build has the entry.
+            eprintln!(
+                "warning: crate `{}` has no fn `{entry}` to write the call graph from",
+                local_crate.name
+            );
+        }
+    }
+
+    let recursion = opts
+        .enabled(Check::Recursion)
+        .then(|| Recursion::new(&call_graph, detect.entries(), tcx));
+    let locks = opts
+        .enabled(Check::Locks)
+        .then(|| Locks::new(&call_graph, detect.entries(), tcx));
+    let isr = opts
+        .enabled(Check::Isr)
+        .then(|| Isr::new(&call_graph, &fn_items, &config, tcx));
+    let stack_depth = opts
+        .enabled(Check::StackDepth)
+        .then(|| StackDepth::new(&call_graph, detect.entries()));
+    let panics_doc = opts
+        .enabled(Check::PanicsDoc)
+        .then(|| PanicsDoc::new(&call_graph, &fn_items, tcx));
 
-    if !spots.is_empty() {
+    let v_spots = call_graph.analyze(&detect, opts.root_causes, opts.witnesses(), tcx);
+    summary::note_partial(&call_graph, opts, &local_crate.name);
+
+    let out = Emitter::new(opts.format, report_file, baseline);
+    if v_spots.iter().any(|s| !s.is_empty())
+        || recursion.as_ref().is_some_and(|r| !r.is_empty())
+        || locks.as_ref().is_some_and(|l| !l.is_empty())
+        || isr.as_ref().is_some_and(|i| !i.is_empty())
+        || stack_depth.as_ref().is_some_and(|s| !s.is_empty())
+        || panics_doc.as_ref().is_some_and(|p| !p.is_empty())
+    {
         let src = SourceCode::new(tcx);
-        spots.emit(&src);
+        for spots in &v_spots {
+            spots.emit(&src, &out);
+        }
+        if let Some(recursion) = &recursion {
+            recursion.emit(&src, &out);
+        }
+        if let Some(locks) = &locks {
+            locks.emit(&src, &out);
+        }
+        if let Some(isr) = &isr {
+            isr.emit(&src, &out);
+        }
+        if let Some(stack_depth) = &stack_depth {
+            stack_depth.emit(&src, &out);
+        }
+        if let Some(panics_doc) = &panics_doc {
+            panics_doc.emit(&src, &out);
+        }
     }
 
-    ControlFlow::Continue(())
+    let report = CrateReport {
+        krate: local_crate.name,
+        test: tcx.sess.is_test_crate(),
+        entries: detect.entries().len(),
+        findings: out.finish(),
+        graph: graph_path,
+    };
+    if let Some(cache) = &cache {
+        cache.store(&report);
+    }
+    write_results(tcx, opts, &report);
+}
+
+fn load_baseline(opts: &Options) -> Baseline {
+    let Some(path) = &opts.baseline else {
+        return Baseline::default();
+    };
+    Baseline::load(path).unwrap_or_else(|err| {
+        eprintln!(
+            "error: failed to load redpen baseline `{}`: {err}",
+            path.display()
+        );
+        std::process::exit(1);
+    })
+}
+
+/// Name of report files, distinguishing test builds from other builds of a crate.
+fn report_name(tcx: TyCtxt, krate: &str) -> String {
+    if tcx.sess.is_test_crate() {
+        format!("{krate}-test")
+    } else {
+        krate.to_owned()
+    }
+}
+
+/// Write the results of the crate for cargo-redpen to aggregate, named after the `.rmeta`
+/// file cargo reports.
+fn write_results(tcx: TyCtxt, opts: &Options, report: &CrateReport) {
+    if let Some(dir) = &opts.results_dir {
+        let name = format!("{}{}.json", report.krate, tcx.sess.opts.cg.extra_filename);
+        write_json(&dir.join(name), report, "results");
+    }
 }
@@ -0,0 +1,230 @@
+use crate::{
+    call_graph::{CallGraph, sink_spots},
+    detect::Rule,
+    diagnostics::{Emitter, SourceCode},
+    fn_item::FnItem,
+};
+use indexmap::IndexSet;
+use redpen::{
+    options::Check,
+    report::{Finding, Level, PanicKind},
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{
+    CrateDef,
+    mir::{Body, MirVisitor, Terminator, TerminatorKind, visit::Location},
+    rustc_internal::{internal, stable},
+    ty::{FnDef, GenericArgKind, RigidTy, Span, TyKind},
+};
+
+/// Public fns whose `# Panics` doc section disagrees with the transitive analysis.
+pub struct PanicsDoc {
+    mismatches: Vec<Mismatch>,
+}
+
+enum Mismatch {
+    /// A fn that may panic without documenting it.
+    Undocumented {
+        fn_item: FnItem,
+        body: Span,
+        /// Spots in the body that may panic.
+        calls: IndexSet<Span>,
+        /// The shortest witness call path to a panic.
+        path: Vec<FnItem>,
+        panic_kind: PanicKind,
+    },
+    /// A fn documenting panics, but reaching none.
+    Stale {
+        fn_item: FnItem,
+        /// From the first doc comment to the fn header.
+        item: Span,
+        /// Doc comments with the `# Panics` heading.
+        section: Span,
+    },
+}
+
+impl PanicsDoc {
+    pub fn new(call_graph: &CallGraph, fn_items: &[FnItem], tcx: TyCtxt) -> Self {
+        let rule = Rule::panic(call_graph, tcx);
+        let sinks: IndexSet<&FnItem> = rule.sinks().collect();
+        let reaching = call_graph.reaching(rule.sinks());
+        let may_reach: IndexSet<FnDef> = reaching.iter().map(|f| f.def).collect();
+
+        let mut mismatches = Vec::new();
+        for fn_item in fn_items {
+            let def_id = internal(tcx, fn_item.def.def_id());
+            let is_pub = def_id
+                .as_local()
+                .is_some_and(|def_id| tcx.effective_visibilities(()).is_exported(def_id));
+            // Trait impl methods are documented by the trait, as clippy's
+            // `missing_panics_doc` assumes.
+            if !is_pub
+                || fn_item.has_redpen_attr("silence_panic")
+                || tcx.trait_impl_of_assoc(def_id).is_some()
+            {
+                continue;
+            }
+            let docs: Vec<_> = tcx
+                .get_all_attrs(def_id)
+                .iter()
+                .filter_map(|attr| Some((attr.doc_str()?, attr.span())))
+                .collect();
+            let section = docs
+                .iter()
+                .find(|(doc, _)| doc.as_str().lines().any(is_panics_heading))
+                .map(|(_, span)| *span);
+
+            match (reaching.contains(fn_item), section) {
+                (true, None) => {
+                    let Some(body) = fn_item.body() else { continue };
+                    let Some(path) = call_graph.shortest_path(fn_item, &sinks) else {
+                        continue;
+                    };
+                    mismatches.push(Mismatch::Undocumented {
+                        fn_item: fn_item.clone(),
+                        body: body.span,
+                        calls: sink_spots(tcx, &body, &may_reach),
+                        panic_kind: call_graph.panic_kind(&path, tcx),
+                        path,
+                    });
+                }
+                // Calls the call graph can't follow may still panic.
+                (false, Some(section)) if !reaches_unresolved_call(call_graph, fn_item, tcx) => {
+                    let header = internal(tcx, fn_item.def.span());
+                    let item = docs.iter().fold(header, |item, (_, doc)| item.to(*doc));
+                    mismatches.push(Mismatch::Stale {
+                        fn_item: fn_item.clone(),
+                        item: stable(item),
+                        section: stable(section),
+                    });
+                }
+                _ => (),
+            }
+        }
+        PanicsDoc { mismatches }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    pub fn emit(&self, src: &SourceCode, out: &Emitter) {
+        for mismatch in &self.mismatches {
+            mismatch.emit(src, out);
+        }
+    }
+}
+
+/// Whether any fn reachable from `fn_item` makes a call whose callee is unknown: through
+/// `dyn` or a fn pointer, or to a fn without a body other than intrinsics and summarized
+/// upstream fns.
+fn reaches_unresolved_call(call_graph: &CallGraph, fn_item: &FnItem, tcx: TyCtxt) -> bool {
+    let entries = [fn_item.clone()];
+    call_graph.reachable_from(&entries).into_iter().any(|f| {
+        if f.def.is_intrinsic() || call_graph.summary(f).is_some() {
+            return false;
+        }
+        let Some(body) = f.body() else {
+            return true;
+        };
+        let mut visitor = UnresolvedCalls {
+            tcx,
+            body: &body,
+            found: false,
+        };
+        visitor.visit_body(&body);
+        visitor.found
+    })
+}
+
+struct UnresolvedCalls<'tcx, 'a> {
+    tcx: TyCtxt<'tcx>,
+    body: &'a Body,
+    found: bool,
+}
+
+impl MirVisitor for UnresolvedCalls<'_, '_> {
+    fn visit_terminator(&mut self, term: &Terminator, location: Location) {
+        if let TerminatorKind::Call { func, .. } = &term.kind
+            && let Ok(ty) = func.ty(self.body.locals())
+        {
+            self.found |= match ty.kind() {
+                TyKind::RigidTy(RigidTy::FnDef(fn_def, args)) => {
+                    // The first generic arg of a trait method is the `Self` type.
+                    let def_id = internal(self.tcx, fn_def.def_id());
+                    self.tcx.trait_of_assoc(def_id).is_some()
+                        && matches!(
+                            args.0.first(),
+                            Some(GenericArgKind::Type(ty))
+                                if matches!(ty.kind(), TyKind::RigidTy(RigidTy::Dynamic(..)))
+                        )
+                }
+                _ => true,
+            };
+        }
+        self.super_terminator(term, location);
+    }
+}
+
+/// Whether a doc line is a heading of the `# Panics` section, at any level.
+fn is_panics_heading(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('#') && line.trim_start_matches('#').trim() == "Panics"
+}
+
+impl Mismatch {
+    fn emit(&self, src: &SourceCode, out: &Emitter) {
+        let finding = match self {
+            Mismatch::Undocumented {
+                fn_item,
+                body,
+                calls,
+                path,
+                panic_kind,
+            } => {
+                let annots = calls
+                    .iter()
+                    .map(|sp| (src.offset(*body, *sp), "This may panic!".to_owned()));
+                let witness = path.iter().map(|f| f.qualified_name()).collect();
+                let path: Vec<_> = path.iter().map(|f| format!("`{}`", f.name)).collect();
+                Finding::new(
+                    Check::PanicsDoc,
+                    Level::Warning,
+                    format!(
+                        "Public function `{}` may panic, but its doc has no `# Panics` section.",
+                        fn_item.name
+                    ),
+                    fn_item.qualified_name(),
+                )
+                .about(["undocumented"])
+                .snippet(
+                    src.snippet(*body, fn_item.def.span(), "For this function.")
+                        .primaries(annots),
+                )
+                .witnesses([witness])
+                .panic_kinds([*panic_kind])
+                .note(format!("May panic through {}", path.join(" -> ")))
+            }
+            Mismatch::Stale {
+                fn_item,
+                item,
+                section,
+            } => Finding::new(
+                Check::PanicsDoc,
+                Level::Warning,
+                format!(
+                    "Public function `{}` documents panics, but none is reachable.",
+                    fn_item.name
+                ),
+                fn_item.qualified_name(),
+            )
+            .about(["stale"])
+            .snippet(
+                src.snippet(*item, fn_item.def.span(), "For this function.")
+                    .primary(src.offset(*item, *section), "This section may be stale."),
+            )
+            .note("No call path from this function reaches a panic"),
+        };
+        out.emit(finding);
+    }
+}
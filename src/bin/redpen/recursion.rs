@@ -0,0 +1,110 @@
+use crate::{
+    call_graph::{CallGraph, call_sites},
+    diagnostics::{Emitter, SourceCode},
+    fn_item::FnItem,
+};
+use indexmap::{IndexMap, IndexSet};
+use redpen::{
+    options::Check,
+    report::{Finding, Level},
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{
+    CrateDef,
+    ty::{FnDef, Span},
+};
+
+/// Tag on any member of a cycle to silence it, e.g. when the recursion is bounded.
+const ALLOW_RECURSION: &str = "allow_recursion";
+
+/// Directly or mutually recursive cycles reachable from entries.
+#[derive(Debug, Default)]
+pub struct Recursion {
+    cycles: Vec<Cycle>,
+}
+
+#[derive(Debug)]
+struct Cycle {
+    members: Vec<FnItem>,
+    /// Recursive call spots in each local member: (fn body span, call spans).
+    calls: IndexMap<FnDef, (Span, IndexSet<Span>)>,
+}
+
+impl Recursion {
+    pub fn new(call_graph: &CallGraph, entries: &[FnItem], tcx: TyCtxt) -> Self {
+        let reached = call_graph.reachable_from(entries);
+        let mut cycles = Vec::new();
+
+        for mut scc in call_graph.sccs(&reached) {
+            let is_recursive = match &scc[..] {
+                [f] => call_graph.callees(f).any(|callee| callee == *f),
+                _ => true,
+            };
+            // Only report cycles we can do something about.
+            if !is_recursive
+                || !scc.iter().any(|f| f.is_local())
+                || scc.iter().any(|f| f.has_redpen_attr(ALLOW_RECURSION))
+            {
+                continue;
+            }
+            scc.sort();
+
+            let defs: IndexSet<FnDef> = scc.iter().map(|f| f.def).collect();
+            let mut calls = IndexMap::new();
+            for f in scc.iter().filter(|f| f.is_local()) {
+                let Some(body) = f.body() else { continue };
+                let spans = call_sites(tcx, &body, &defs);
+                if !spans.is_empty() {
+                    calls.insert(f.def, (body.span, spans));
+                }
+            }
+
+            let members = scc.into_iter().cloned().collect();
+            cycles.push(Cycle { members, calls });
+        }
+
+        cycles.sort_by(|a, b| a.members.cmp(&b.members));
+        Recursion { cycles }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    pub fn emit(&self, src: &SourceCode, out: &Emitter) {
+        for cycle in &self.cycles {
+            cycle.emit(src, out);
+        }
+    }
+}
+
+impl Cycle {
+    fn emit(&self, src: &SourceCode, out: &Emitter) {
+        let snippets = self.calls.iter().map(|(f, (body, calls))| {
+            let annot_call = |sp: Span| (src.offset(*body, sp), "This call recurses.".to_owned());
+            src.snippet(*body, f.span(), "For this recursive function.")
+                .primaries(calls.iter().copied().map(annot_call))
+        });
+
+        let members = self
+            .members
+            .iter()
+            .map(|f| format!("`{}`", f.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let finding = Finding::new(
+            Check::Recursion,
+            Level::Error,
+            "A recursive call cycle is found.",
+            self.members[0].qualified_name(),
+        )
+        .about(self.members.iter().map(|f| f.qualified_name()))
+        .snippets(snippets)
+        .note(format!("Cycle members: {members}."))
+        .help(format!(
+            "Tag a member with `#[redpen::{ALLOW_RECURSION}]` if the recursion is bounded."
+        ));
+        out.emit(finding);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    call_graph::CallGraph,
+    diagnostics::{Emitter, SourceCode},
+    fn_item::FnItem,
+};
+use indexmap::{IndexMap, IndexSet};
+use redpen::{
+    options::Check,
+    report::{Finding, Level},
+};
+use rustc_public::CrateDef;
+
+/// Estimated worst-case call depth and stack usage from each entry.
+///
+/// Frame sizes are the sum of layouts of all MIR locals, which overestimates
+/// frames whose locals share stack slots, and is unknown for fns without bodies
+/// or with generic locals. Such frames only count as a lower bound.
+pub struct StackDepth {
+    entries: Vec<FnItem>,
+    frames: IndexMap<FnItem, Frame>,
+    deepest: IndexMap<FnItem, Deepest>,
+}
+
+/// Stack frame size of a fn item.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    bytes: usize,
+    /// False if the layout of some locals is unknown.
+    exact: bool,
+}
+
+/// The deepest call path starting from a fn item.
+#[derive(Debug, Clone)]
+struct Deepest {
+    depth: usize,
+    bytes: usize,
+    exact: bool,
+    /// The path contains a cycle, so the real depth is unbounded.
+    recursive: bool,
+    /// The next fn item on the path.
+    next: Option<FnItem>,
+}
+
+impl StackDepth {
+    pub fn new(call_graph: &CallGraph, entries: &[FnItem]) -> Self {
+        let mut search = Search {
+            call_graph,
+            frames: IndexMap::new(),
+            deepest: IndexMap::new(),
+            visiting: IndexSet::new(),
+        };
+        for entry in entries {
+            search.deepest(entry);
+        }
+        StackDepth {
+            entries: entries.to_vec(),
+            frames: search.frames,
+            deepest: search.deepest,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn emit(&self, src: &SourceCode, out: &Emitter) {
+        for entry in &self.entries {
+            self.emit_entry(entry, src, out);
+        }
+    }
+
+    fn emit_entry(&self, entry: &FnItem, src: &SourceCode, out: &Emitter) {
+        // Follow the deepest path. Memoized paths may run into a cycle, so stop at
+        // the first repeated fn item.
+        let mut path = IndexSet::new();
+        let mut node = Some(entry);
+        let mut repeated = None;
+        while let Some(f) = node {
+            if !path.insert(f) {
+                repeated = Some(f);
+                break;
+            }
+            node = self.deepest.get(f).and_then(|d| d.next.as_ref());
+        }
+
+        let frames = path.iter().map(|f| self.frames[*f]);
+        let bytes: usize = frames.clone().map(|frame| frame.bytes).sum();
+        let at_least = if frames.clone().all(|frame| frame.exact) {
+            ""
+        } else {
+            "at least "
+        };
+        let mut title = format!(
+            "The deepest call path from `{}` has {} frames, using {at_least}{bytes} bytes of stack.",
+            entry.name,
+            path.len(),
+        );
+        if repeated.is_some() || self.deepest[entry].recursive {
+            title.push_str(" The path is recursive, so the real depth is unbounded.");
+        }
+
+        let mut calls: Vec<_> = path
+            .iter()
+            .map(|f| {
+                let frame = self.frames[*f];
+                let at_least = if frame.exact { "" } else { "at least " };
+                format!("`{}` ({at_least}{} bytes)", f.name, frame.bytes)
+            })
+            .collect();
+        if let Some(f) = repeated {
+            calls.push(format!("`{}` (recursion)", f.name));
+        }
+
+        let finding = Finding::new(
+            Check::StackDepth,
+            Level::Note,
+            title,
+            entry.qualified_name(),
+        )
+        .origin(src.origin(entry.def.span()))
+        .note(format!("Call path: {}", calls.join("\n  -> ")));
+        out.emit(finding);
+    }
+}
+
+struct Search<'g> {
+    call_graph: &'g CallGraph,
+    frames: IndexMap<FnItem, Frame>,
+    deepest: IndexMap<FnItem, Deepest>,
+    visiting: IndexSet<FnItem>,
+}
+
+impl<'g> Search<'g> {
+    /// Find the deepest path from each fn item reachable from the entry depth-first, with
+    /// an explicit stack of callers, since deep call chains would overflow the native stack.
+    fn deepest(&mut self, entry: &FnItem) {
+        if self.deepest.contains_key(entry) {
+            return;
+        }
+        let mut visits = vec![self.visit(entry)];
+        while let Some(visit) = visits.last_mut() {
+            if let Some(callee) = visit.callees.next() {
+                if let Some(deepest) = self.deepest.get(callee) {
+                    visit.consider(callee, deepest.clone());
+                } else if self.visiting.contains(callee) {
+                    // Back to a fn item on the current path: stop here.
+                    let deepest = Deepest {
+                        depth: 0,
+                        bytes: 0,
+                        exact: true,
+                        recursive: true,
+                        next: None,
+                    };
+                    visit.consider(callee, deepest);
+                } else {
+                    visits.push(self.visit(callee));
+                }
+                continue;
+            }
+
+            let Some(visit) = visits.pop() else { break };
+            self.visiting.swap_remove(&visit.fn_item);
+            let deepest = visit.deepest();
+            if let Some(caller) = visits.last_mut() {
+                caller.consider(&visit.fn_item, deepest.clone());
+            }
+            self.deepest.insert(visit.fn_item, deepest);
+        }
+    }
+
+    fn visit(&mut self, fn_item: &FnItem) -> Visit<'g> {
+        self.visiting.insert(fn_item.clone());
+        let call_graph = self.call_graph;
+        Visit {
+            fn_item: fn_item.clone(),
+            frame: self.frame(fn_item),
+            callees: call_graph.callees(fn_item).collect::<Vec<_>>().into_iter(),
+            recursive: false,
+            longest: None,
+        }
+    }
+
+    fn frame(&mut self, fn_item: &FnItem) -> Frame {
+        if let Some(frame) = self.frames.get(fn_item) {
+            return *frame;
+        }
+        let mut frame = Frame {
+            bytes: 0,
+            exact: true,
+        };
+        match fn_item.body() {
+            Some(body) => {
+                for local in body.locals() {
+                    match local.ty.layout() {
+                        Ok(layout) => frame.bytes += layout.shape().size.bytes(),
+                        Err(_) => frame.exact = false,
+                    }
+                }
+            }
+            None => frame.exact = false,
+        }
+        self.frames.insert(fn_item.clone(), frame);
+        frame
+    }
+}
+
+/// A fn item on the search stack, with its callees left to visit.
+struct Visit<'g> {
+    fn_item: FnItem,
+    frame: Frame,
+    callees: std::vec::IntoIter<&'g FnItem>,
+    recursive: bool,
+    longest: Option<(FnItem, Deepest)>,
+}
+
+impl Visit<'_> {
+    fn consider(&mut self, callee: &FnItem, deepest: Deepest) {
+        self.recursive |= deepest.recursive;
+        let is_deeper = self
+            .longest
+            .as_ref()
+            .is_none_or(|(_, d)| (deepest.depth, deepest.bytes) > (d.depth, d.bytes));
+        if is_deeper {
+            self.longest = Some((callee.clone(), deepest));
+        }
+    }
+
+    fn deepest(&self) -> Deepest {
+        let frame = self.frame;
+        match &self.longest {
+            Some((callee, d)) => Deepest {
+                depth: d.depth + 1,
+                bytes: d.bytes + frame.bytes,
+                exact: d.exact && frame.exact,
+                recursive: self.recursive,
+                next: Some(callee.clone()),
+            },
+            None => Deepest {
+                depth: 1,
+                bytes: frame.bytes,
+                exact: frame.exact,
+                recursive: self.recursive,
+                next: None,
+            },
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Per-crate summaries of what each fn may reach, so downstream crates can stop at
+//! summarized fns instead of walking their bodies again.
+//!
+//! A summary is written to `<dir>/<crate>-<svh>.json` for every crate analyzed by
+//! cargo-redpen, and for dependencies unless `--no-summarize-deps`, and loaded by crates
+//! depending on it. Sysroot crates aren't compiled by cargo, so the summaries of std, core
+//! and alloc only hold fns reached by crates analyzed so far, and other fns are walked.
+//!
+//! Only sink sets are summarized: callees, lock events and stack frames of summarized fns
+//! are not known downstream, so recursion and lock orders across crates are not checked,
+//! and stack depths stop at them, as [`note_partial`] tells.
+
+use crate::{
+    cache::write_json,
+    call_graph::{CallGraph, SinkPaths},
+    config::Config,
+    detect::{self, Detect, Rule},
+    fn_item::FnItem,
+    isr,
+};
+use redpen::{
+    options::{Check, Options},
+    report::PanicKind,
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{CrateDef, rustc_internal::internal};
+use rustc_span::def_id::{CrateNum, LOCAL_CRATE};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CrateSummary {
+    #[serde(rename = "crate")]
+    krate: String,
+    svh: String,
+    /// Sink sets the summary is computed for, like `panic` or `forbid:std::process`.
+    sinks: Vec<String>,
+    /// All local fns, including those reaching no sinks.
+    functions: Vec<FnSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FnSummary {
+    /// Hex of the `DefPathHash`, which identifies the fn across crates.
+    hash: String,
+    name: String,
+    /// The shortest witness path to each reached sink set, from this fn to a sink.
+    reaches: BTreeMap<String, Vec<String>>,
+    /// Kind of the panic the witness path to a panic ends in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    panic_kind: Option<PanicKind>,
+}
+
+impl CrateSummary {
+    fn covers(&self, sinks: &[String]) -> bool {
+        sinks.iter().all(|s| self.sinks.contains(s))
+    }
+}
+
+impl FnSummary {
+    /// Fn names from this fn to a sink of the given set, if any is reached.
+    pub fn witness(&self, sinks: &str) -> Option<&[String]> {
+        self.reaches.get(sinks).map(|path| &path[..])
+    }
+
+    pub fn panic_kind(&self) -> Option<PanicKind> {
+        self.panic_kind
+    }
+}
+
+/// Summaries of upstream crates.
+pub struct Summaries<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    crates: HashMap<CrateNum, HashMap<String, FnSummary>>,
+}
+
+impl<'tcx> Summaries<'tcx> {
+    /// Load summaries of upstream crates computed for all the needed sink sets. Crates
+    /// without such a summary are walked as before.
+    pub fn load(tcx: TyCtxt<'tcx>, dir: Option<&Path>, sinks: &[String]) -> Self {
+        let mut crates = HashMap::new();
+        let Some(dir) = dir else {
+            return Summaries { tcx, crates };
+        };
+        for &cnum in tcx.crates(()) {
+            let Some(summary) = read(dir, tcx, cnum) else {
+                continue;
+            };
+            if summary.covers(sinks) {
+                let fns = summary
+                    .functions
+                    .into_iter()
+                    .map(|f| (f.hash.clone(), f))
+                    .collect();
+                crates.insert(cnum, fns);
+            }
+        }
+        Summaries { tcx, crates }
+    }
+
+    pub fn get(&self, f: &FnItem) -> Option<&FnSummary> {
+        if f.is_local() || self.crates.is_empty() {
+            return None;
+        }
+        let def_id = internal(self.tcx, f.def.def_id());
+        let fns = self.crates.get(&def_id.krate)?;
+        fns.get(&self.tcx.def_path_hash(def_id).0.to_hex())
+    }
+}
+
+/// Note the enabled checks that stop at summarized fns, if any is reached. Sysroot fns only
+/// count for stack depths, as locks are not walked into the sysroot anyway, and recursion
+/// is only reported in cycles through local fns.
+pub fn note_partial(call_graph: &CallGraph, opts: &Options, krate: &str) {
+    let checks: Vec<_> = [Check::Recursion, Check::Locks, Check::StackDepth]
+        .into_iter()
+        .filter(|&check| opts.enabled(check))
+        .map(|check| format!("`{}`", check.name()))
+        .collect();
+    let mut summarized = call_graph
+        .summarized()
+        .map(|(f, _)| f)
+        .filter(|f| opts.enabled(Check::StackDepth) || !f.is_sysroot());
+    let Some(first) = summarized.next() else {
+        return;
+    };
+    if !checks.is_empty() {
+        eprintln!(
+            "note: {} in crate `{krate}` stop at upstream fns known from summaries only, \
+             like `{}` ({} in total)",
+            checks.join(", "),
+            first.qualified_name(),
+            1 + summarized.count()
+        );
+    }
+}
+
+/// Whether the local crate has been summarized for all the needed sink sets.
+pub fn is_written(tcx: TyCtxt, dir: &Path, sinks: &[String]) -> bool {
+    read(dir, tcx, LOCAL_CRATE).is_some_and(|summary| summary.covers(sinks))
+}
+
+/// Names of sink sets needed by enabled checks: rule keys and ISR properties.
+pub fn sink_keys(config: &Config, opts: &Options) -> Vec<String> {
+    let mut keys = Vec::new();
+    if opts.enabled(Check::Panic) || opts.enabled(Check::PanicsDoc) {
+        keys.push(detect::PANIC.to_owned());
+    }
+    if opts.enabled(Check::Forbid) {
+        keys.extend(config.forbid.iter().map(|f| detect::forbid_key(&f.path)));
+    }
+    if opts.enabled(Check::Isr) {
+        keys.extend(isr::properties(config));
+    }
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Sink sets named by [`sink_keys`], with their sinks in the call graph.
+pub fn sink_sets(
+    call_graph: &CallGraph,
+    detect: &Detect,
+    config: &Config,
+    opts: &Options,
+    tcx: TyCtxt,
+) -> Vec<(String, Vec<FnItem>)> {
+    let mut sets: Vec<_> = detect
+        .rules()
+        .iter()
+        .map(|rule| (rule.key(), rule.sinks().cloned().collect()))
+        .collect();
+    if opts.enabled(Check::PanicsDoc) {
+        let rule = Rule::panic(call_graph, tcx);
+        sets.push((rule.key(), rule.sinks().cloned().collect()));
+    }
+    if opts.enabled(Check::Isr) {
+        sets.extend(isr::sink_sets(call_graph, config, tcx));
+    }
+    sets.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+    sets.dedup_by(|(k1, _), (k2, _)| k1 == k2);
+    sets
+}
+
+/// Write the summary of local fns, and merge reached sysroot fns into the summaries of
+/// their crates.
+pub fn write(
+    tcx: TyCtxt,
+    dir: &Path,
+    call_graph: &CallGraph,
+    fn_items: &[FnItem],
+    sink_sets: &[(String, Vec<FnItem>)],
+) {
+    let sets: Vec<(&str, SinkPaths)> = sink_sets
+        .iter()
+        .map(|(key, sinks)| (&**key, call_graph.sink_paths(sinks)))
+        .collect();
+    let sinks: Vec<String> = sink_sets.iter().map(|(key, _)| key.clone()).collect();
+
+    let summary = CrateSummary {
+        krate: tcx.crate_name(LOCAL_CRATE).to_string(),
+        svh: tcx.crate_hash(LOCAL_CRATE).to_string(),
+        sinks: sinks.clone(),
+        functions: fn_items
+            .iter()
+            .map(|f| summarize(tcx, call_graph, &sets, f))
+            .collect(),
+    };
+    write_json(&summary_path(dir, tcx, LOCAL_CRATE), &summary, "summary");
+
+    // Sysroot crates are not compiled by cargo, so they are summarized piecemeal by the
+    // crates walking their fns. Fns already summarized are left as they are.
+    let mut sysroot: BTreeMap<CrateNum, Vec<&FnItem>> = BTreeMap::new();
+    for f in call_graph
+        .fn_items()
+        .filter(|f| f.is_sysroot() && call_graph.summary(f).is_none())
+    {
+        let cnum = internal(tcx, f.def.def_id()).krate;
+        sysroot.entry(cnum).or_default().push(f);
+    }
+    for (cnum, fns) in sysroot {
+        let mut functions: BTreeMap<String, FnSummary> = read(dir, tcx, cnum)
+            .filter(|summary| summary.sinks == sinks)
+            .into_iter()
+            .flat_map(|summary| summary.functions)
+            .map(|f| (f.hash.clone(), f))
+            .collect();
+        for f in fns {
+            let f = summarize(tcx, call_graph, &sets, f);
+            functions.insert(f.hash.clone(), f);
+        }
+        let summary = CrateSummary {
+            krate: tcx.crate_name(cnum).to_string(),
+            svh: tcx.crate_hash(cnum).to_string(),
+            sinks: sinks.clone(),
+            functions: functions.into_values().collect(),
+        };
+        write_json(&summary_path(dir, tcx, cnum), &summary, "summary");
+    }
+}
+
+fn summarize(
+    tcx: TyCtxt,
+    call_graph: &CallGraph,
+    sets: &[(&str, SinkPaths)],
+    f: &FnItem,
+) -> FnSummary {
+    let mut reaches = BTreeMap::new();
+    let mut panic_kind = None;
+    for (key, sink_paths) in sets {
+        let Some(path) = sink_paths.path(f) else {
+            continue;
+        };
+        if *key == detect::PANIC {
+            panic_kind = Some(call_graph.panic_kind(&path, tcx));
+        }
+        reaches.insert(key.to_string(), call_graph.witness(&path, key));
+    }
+    let def_id = internal(tcx, f.def.def_id());
+    FnSummary {
+        hash: tcx.def_path_hash(def_id).0.to_hex(),
+        name: f.qualified_name(),
+        reaches,
+        panic_kind,
+    }
+}
+
+fn read(dir: &Path, tcx: TyCtxt, cnum: CrateNum) -> Option<CrateSummary> {
+    let json = std::fs::read_to_string(summary_path(dir, tcx, cnum)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+fn summary_path(dir: &Path, tcx: TyCtxt, cnum: CrateNum) -> PathBuf {
+    dir.join(format!(
+        "{}-{}.json",
+        tcx.crate_name(cnum),
+        tcx.crate_hash(cnum)
+    ))
+}
+
+/// Sinks of a set found in the summaries of upstream fns in the call graph.
+pub fn summarized_sinks(call_graph: &CallGraph, key: &str) -> Vec<FnItem> {
+    call_graph
+        .summarized()
+        .filter(|(_, s)| s.witness(key).is_some())
+        .map(|(f, _)| f.clone())
+        .collect()
+}
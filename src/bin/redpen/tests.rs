@@ -0,0 +1,65 @@
+use crate::fn_item::FnItem;
+use rustc_hir::def::DefKind;
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{CrateDef, rustc_internal::internal};
+use rustc_span::{
+    def_id::{DefId, LocalDefId},
+    sym,
+};
+use std::collections::HashSet;
+
+/// Fns tagged with `#[test]` in a test build.
+///
+/// The attribute is gone after expansion, but the test harness generates a const of the
+/// same name next to each test fn, marked with `#[rustc_test_marker]`. The test fn is the
+/// free fn of that name in the same module, not a method nor a fn nested in a body.
+pub struct TestFns<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    fns: HashSet<LocalDefId>,
+    /// `main` generated by the test harness.
+    harness_main: Option<DefId>,
+}
+
+impl<'tcx> TestFns<'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>) -> Self {
+        let mut fns = HashSet::new();
+        let mut harness_main = None;
+        if tcx.sess.is_test_crate() {
+            harness_main = tcx.entry_fn(()).map(|(def_id, _)| def_id);
+            let items = tcx.hir_crate_items(());
+            let key = |def_id: LocalDefId| (tcx.local_parent(def_id), tcx.item_name(def_id));
+            let markers: HashSet<_> = items
+                .free_items()
+                .map(|item| item.owner_id.def_id)
+                .filter(|&def_id| tcx.has_attr(def_id, sym::rustc_test_marker))
+                .map(key)
+                .collect();
+            fns = items
+                .free_items()
+                .map(|item| item.owner_id.def_id)
+                .filter(|&def_id| {
+                    tcx.def_kind(def_id) == DefKind::Fn && markers.contains(&key(def_id))
+                })
+                .collect();
+        }
+        TestFns {
+            tcx,
+            fns,
+            harness_main,
+        }
+    }
+
+    pub fn is_harness_main(&self, fn_item: &FnItem) -> bool {
+        self.harness_main
+            .is_some_and(|main| main == internal(self.tcx, fn_item.def.def_id()))
+    }
+
+    pub fn contains(&self, fn_item: &FnItem) -> bool {
+        if self.fns.is_empty() {
+            return false;
+        }
+        internal(self.tcx, fn_item.def.def_id())
+            .as_local()
+            .is_some_and(|def_id| self.fns.contains(&def_id))
+    }
+}
@@ -0,0 +1,5 @@
+//! Types shared by the `redpen` driver and `cargo-redpen`.
+
+pub mod baseline;
+pub mod options;
+pub mod report;
@@ -0,0 +1,333 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Env var set by cargo-redpen, holding [`Options`] in JSON.
+pub const ENV_OPTIONS: &str = "REDPEN_OPTIONS";
+
+/// Redpen-specific options, passed to the driver in JSON through [`ENV_OPTIONS`], or as
+/// `--redpen-<name>[=<value>]` in the argument list.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Options {
+    /// Checks to run, all but [`Check::StackDepth`] and [`Check::PanicsDoc`] by default.
+    pub checks: Vec<Check>,
+    /// Path to the config file, `redpen.toml` by default.
+    pub config: Option<PathBuf>,
+    /// How findings are printed.
+    pub format: Format,
+    /// Which local fns are entries to report.
+    pub entries: Entries,
+    /// Don't analyze `#[test]` fns as entries. Helpers they call are still analyzed.
+    pub skip_tests: bool,
+    /// Report each spot calling into a sink once, at the local fn it's in, with the
+    /// entries reaching that fn as notes, instead of reporting every entry on the way.
+    pub root_causes: bool,
+    /// Also write findings as plain text to `<report_dir>/<crate>.txt`.
+    pub report_dir: Option<PathBuf>,
+    /// Load summaries of upstream crates from, and write the summary of this crate to
+    /// `<summary_dir>/<crate>-<svh>.json`.
+    pub summary_dir: Option<PathBuf>,
+    /// Replay findings of unchanged crates from, and store new findings to
+    /// `<cache_dir>/<crate>-<svh>-<key>.json`.
+    pub cache_dir: Option<PathBuf>,
+    /// Write a [`CrateReport`](crate::report::CrateReport) in JSON to
+    /// `<results_dir>/<crate><extra-filename>.json`, named after the `.rmeta` file.
+    pub results_dir: Option<PathBuf>,
+    /// Creation time of the output directories, which changes the options after they are
+    /// cleaned, so that cargo reruns fresh crates.
+    pub epoch: u64,
+    /// Only write the summary, without reporting findings, as done for dependencies.
+    pub summary_only: bool,
+    /// Don't report findings known in this [`Baseline`](crate::baseline::Baseline) file.
+    pub baseline: Option<PathBuf>,
+    /// Also write the call graph to `<graph_dir>/<crate>.<ext>` in this format.
+    pub graph: Option<GraphFormat>,
+    /// Where to write call graphs, the current directory by default.
+    pub graph_dir: Option<PathBuf>,
+    /// Only write the subgraph on call paths from this fn to panics, e.g. `utils::parse`.
+    pub graph_entry: Option<String>,
+}
+
+/// Checkers selected by `--redpen-checks=<name>,...`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    Panic,
+    Forbid,
+    Recursion,
+    Locks,
+    Isr,
+    /// Report the deepest call path and estimated stack usage of each entry.
+    StackDepth,
+    /// Report public fns whose `# Panics` doc section disagrees with the analysis.
+    PanicsDoc,
+}
+
+impl Check {
+    pub const ALL: [Check; 7] = [
+        Check::Panic,
+        Check::Forbid,
+        Check::Recursion,
+        Check::Locks,
+        Check::Isr,
+        Check::StackDepth,
+        Check::PanicsDoc,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::Panic => "panic",
+            Check::Forbid => "forbid",
+            Check::Recursion => "recursion",
+            Check::Locks => "locks",
+            Check::Isr => "isr",
+            Check::StackDepth => "stack-depth",
+            Check::PanicsDoc => "panics-doc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Check> {
+        Check::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// What the check reports, shown by `cargo redpen explain`.
+    pub fn description(self) -> &'static str {
+        match self {
+            Check::Panic => {
+                "Report spots in local functions that may reach a panic, like `Vec::push` \
+                 on capacity overflow. Silence a function with `#[redpen::silence_panic]`."
+            }
+            Check::Forbid => {
+                "Report calls that may reach functions forbidden by `[[forbid]]` entries in \
+                 redpen.toml."
+            }
+            Check::Recursion => {
+                "Report recursive call cycles reachable from entries. Tag a member \
+                 with `#[redpen::allow_recursion]` if the recursion is bounded."
+            }
+            Check::Locks => {
+                "Report locks acquired in inconsistent orders, and RefCells borrowed while \
+                 already mutably borrowed."
+            }
+            Check::Isr => {
+                "Report `#[redpen::isr]` handlers that may panic, allocate, acquire a lock or \
+                 call functions listed in `isr.non-reentrant` of redpen.toml."
+            }
+            Check::StackDepth => {
+                "Note the deepest call path and estimated stack usage of each entry. \
+                 Not run by default."
+            }
+            Check::PanicsDoc => {
+                "Report public functions that may panic without a `# Panics` doc section, and \
+                 `# Panics` sections of functions reaching no panic nor any call through \
+                 `dyn` or a fn pointer. Trait impl methods are skipped. Not run by default."
+            }
+        }
+    }
+}
+
+/// Output format of findings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    /// Rendered diagnostics on stderr.
+    #[default]
+    Human,
+    /// One [`Finding`](crate::report::Finding) in JSON per line on stdout.
+    Json,
+    /// One [`OsCheckerFinding`](crate::report::OsCheckerFinding) in JSON per line on
+    /// stdout, for os-checker to ingest.
+    OsChecker,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "human" => Some(Format::Human),
+            "json" => Some(Format::Json),
+            "os-checker" => Some(Format::OsChecker),
+            _ => None,
+        }
+    }
+}
+
+/// Local fns to report findings for, selected by `--redpen-entries=<mode>`. Other fns are
+/// still walked when called from entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Entries {
+    /// All local fns, including private helpers.
+    #[default]
+    All,
+    /// Fns reachable from other crates, i.e. the public API.
+    Pub,
+    /// `main`, `#[test]` fns, and fns exported by `#[no_mangle]` or `#[export_name]`.
+    Exports,
+    /// Fns tagged with `#[redpen::entry]`.
+    Tagged,
+}
+
+impl Entries {
+    pub const ALL: [Entries; 4] = [
+        Entries::All,
+        Entries::Pub,
+        Entries::Exports,
+        Entries::Tagged,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Entries::All => "all",
+            Entries::Pub => "pub",
+            Entries::Exports => "exports",
+            Entries::Tagged => "tagged",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Entries> {
+        Entries::ALL.into_iter().find(|e| e.name() == name)
+    }
+}
+
+/// Format of call graph exports, selected by `--redpen-graph=<format>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GraphFormat {
+    /// Graphviz DOT, to render with `dot -Tsvg`.
+    Dot,
+    /// Nodes and edges with call sites in JSON.
+    Json,
+    /// The same as JSON, in GraphML for graph tools.
+    Graphml,
+}
+
+impl GraphFormat {
+    pub const ALL: [GraphFormat; 3] = [GraphFormat::Dot, GraphFormat::Json, GraphFormat::Graphml];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Json => "json",
+            GraphFormat::Graphml => "graphml",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GraphFormat> {
+        GraphFormat::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// Extension of exported files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Json => "graph.json",
+            GraphFormat::Graphml => "graphml",
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            checks: Check::ALL
+                .into_iter()
+                .filter(|c| !matches!(c, Check::StackDepth | Check::PanicsDoc))
+                .collect(),
+            config: None,
+            format: Format::default(),
+            entries: Entries::default(),
+            skip_tests: false,
+            root_causes: false,
+            report_dir: None,
+            summary_dir: None,
+            cache_dir: None,
+            results_dir: None,
+            epoch: 0,
+            summary_only: false,
+            baseline: None,
+            graph: None,
+            graph_dir: None,
+            graph_entry: None,
+        }
+    }
+}
+
+impl Options {
+    /// Read options from [`ENV_OPTIONS`], and take redpen flags out of the argument list,
+    /// leaving the rest to rustc. Flags in the argument list take precedence.
+    pub fn take_from(args: &mut Vec<String>) -> Self {
+        let mut opts = match std::env::var(ENV_OPTIONS) {
+            Ok(json) => serde_json::from_str(&json)
+                .unwrap_or_else(|err| error(&format!("invalid `{ENV_OPTIONS}`: {err}"))),
+            Err(_) => Options::default(),
+        };
+        args.retain(|arg| match arg.strip_prefix("--redpen-") {
+            Some(flag) => {
+                opts.set(flag);
+                false
+            }
+            None => true,
+        });
+        opts
+    }
+
+    pub fn enabled(&self, check: Check) -> bool {
+        self.checks.contains(&check)
+    }
+
+    /// Whether findings carry witness paths, which only JSON findings and the HTML report
+    /// show.
+    pub fn witnesses(&self) -> bool {
+        self.format == Format::Json || self.report_dir.is_some()
+    }
+
+    fn set(&mut self, flag: &str) {
+        let (name, value) = flag.split_once('=').unwrap_or((flag, ""));
+        match name {
+            "stack-depth" if !self.enabled(Check::StackDepth) => {
+                self.checks.push(Check::StackDepth)
+            }
+            "stack-depth" => (),
+            "checks" => {
+                self.checks = value
+                    .split(',')
+                    .map(|name| {
+                        Check::from_name(name)
+                            .unwrap_or_else(|| error(&format!("unknown redpen check `{name}`")))
+                    })
+                    .collect()
+            }
+            "config" => self.config = Some(value.into()),
+            "format" => {
+                self.format = Format::from_name(value)
+                    .unwrap_or_else(|| error(&format!("unknown redpen format `{value}`")))
+            }
+            "report-dir" => self.report_dir = Some(value.into()),
+            "summary-dir" => self.summary_dir = Some(value.into()),
+            "cache-dir" => self.cache_dir = Some(value.into()),
+            "entries" => {
+                self.entries = Entries::from_name(value)
+                    .unwrap_or_else(|| error(&format!("unknown redpen entries `{value}`")))
+            }
+            "skip-tests" => self.skip_tests = true,
+            "root-causes" => self.root_causes = true,
+            "results-dir" => self.results_dir = Some(value.into()),
+            "summary-only" => self.summary_only = true,
+            "baseline" => self.baseline = Some(value.into()),
+            "graph" => {
+                self.graph = Some(
+                    GraphFormat::from_name(value)
+                        .unwrap_or_else(|| error(&format!("unknown redpen graph `{value}`"))),
+                )
+            }
+            "graph-dir" => self.graph_dir = Some(value.into()),
+            "graph-entry" => self.graph_entry = Some(value.into()),
+            _ => error(&format!("unknown redpen flag `--redpen-{flag}`")),
+        }
+    }
+}
+
+fn error(msg: &str) -> ! {
+    eprintln!("error: {msg}");
+    std::process::exit(1);
+}
@@ -0,0 +1,382 @@
+use crate::options::Check;
+use annotate_snippets::{self as annotate, Origin, Renderer};
+use serde::{Deserialize, Serialize};
+use std::{ops::Range, path::PathBuf};
+
+/// Results of analyzing a crate, written by the driver for cargo-redpen to aggregate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateReport {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    /// Whether the crate is built with `--test`, e.g. for unit tests or benches.
+    pub test: bool,
+    /// Number of local functions analyzed as entries.
+    pub entries: usize,
+    pub findings: Vec<Finding>,
+    /// Where the call graph of the crate is written, unless `--redpen-graph-entry` names
+    /// no fn of the crate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub graph: Option<PathBuf>,
+}
+
+/// A diagnostic reported by a check, printed by the driver in human or JSON format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    /// Stable identifier of the finding, from the check, the function and what the finding
+    /// is about, like the callees reaching a panic and the kinds of panics. Line numbers are left out, so that it
+    /// survives unrelated edits, e.g. in baselines.
+    pub id: String,
+    /// Hash of the id and the code at primary annotations with whitespace normalized, set
+    /// when the finding is emitted. Unlike the id, it changes when the reported code
+    /// changes, but like the id, it doesn't change when the code only moves.
+    pub fingerprint: String,
+    pub check: Check,
+    pub level: Level,
+    pub title: String,
+    /// Path to the function the finding is reported for.
+    pub function: String,
+    pub snippets: Vec<Snippet>,
+    /// Location to show when there is no snippet.
+    pub origin: Option<Location>,
+    pub notes: Vec<Note>,
+    /// Call paths from the function to what the finding is about, like panics, by name.
+    #[serde(default)]
+    pub witnesses: Vec<Vec<String>>,
+    /// Kind of the panic each witness ends in, for panic findings.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub panic_kinds: Vec<PanicKind>,
+}
+
+/// A finding in the per-tool schema of os-checker, which collects diagnostics of static
+/// analysis tools by file and line, along with their raw text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsCheckerFinding {
+    /// File of [`Finding::location`], if any.
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    /// Name of the check, like `panic`.
+    pub kind: String,
+    pub message: String,
+    /// The finding rendered as plain text.
+    pub raw: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+/// What a panic is about, from the std fns on the call path to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PanicKind {
+    /// Out of bounds indexing or slicing.
+    Bounds,
+    /// `unwrap` or `expect` on `None` or `Err`.
+    Unwrap,
+    /// Arithmetic overflow or division by zero.
+    Arithmetic,
+    /// A failed `assert_eq!` or `assert_ne!`.
+    Assert,
+    /// Capacity overflow or allocation failure.
+    Alloc,
+    /// A `RefCell` already borrowed.
+    Borrow,
+    /// A broken precondition of an unsafe fn, checked in debug builds.
+    Precondition,
+    /// Any other panic, like `panic!` or `unreachable!`.
+    Explicit,
+}
+
+impl PanicKind {
+    pub const ALL: [PanicKind; 8] = [
+        PanicKind::Bounds,
+        PanicKind::Unwrap,
+        PanicKind::Arithmetic,
+        PanicKind::Assert,
+        PanicKind::Alloc,
+        PanicKind::Borrow,
+        PanicKind::Precondition,
+        PanicKind::Explicit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PanicKind::Bounds => "bounds",
+            PanicKind::Unwrap => "unwrap",
+            PanicKind::Arithmetic => "arithmetic",
+            PanicKind::Assert => "assert",
+            PanicKind::Alloc => "alloc",
+            PanicKind::Borrow => "borrow",
+            PanicKind::Precondition => "precondition",
+            PanicKind::Explicit => "explicit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub file: String,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column in chars.
+    pub column: usize,
+}
+
+/// Source code of a function, with annotated spans.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snippet {
+    pub file: String,
+    /// Line number of the first line of `source`.
+    pub line_start: usize,
+    /// Column of the first char of `source`.
+    pub column_start: usize,
+    pub source: String,
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    /// Primary annotations point at the reported spots, and context ones explain them.
+    pub primary: bool,
+    /// Byte range in the snippet source.
+    pub range: Range<usize>,
+    pub label: String,
+    /// Start of the annotated span.
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub level: Level,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn new(check: Check, level: Level, title: impl Into<String>, function: String) -> Self {
+        Finding {
+            id: format!("{}:{function}", check.name()),
+            fingerprint: String::new(),
+            check,
+            level,
+            title: title.into(),
+            function,
+            snippets: Vec::new(),
+            origin: None,
+            notes: Vec::new(),
+            witnesses: Vec::new(),
+            panic_kinds: Vec::new(),
+        }
+    }
+
+    /// Add what the finding is about to its id, like callees or lock classes. They are
+    /// sorted, so the id doesn't depend on the order they are found in.
+    pub fn about<S: Into<String>>(mut self, subjects: impl IntoIterator<Item = S>) -> Self {
+        let mut subjects: Vec<String> = subjects.into_iter().map(Into::into).collect();
+        subjects.sort();
+        subjects.dedup();
+        self.id = format!("{}/{}", self.id, subjects.join(","));
+        self
+    }
+
+    pub fn snippet(mut self, snippet: Snippet) -> Self {
+        self.snippets.push(snippet);
+        self
+    }
+
+    pub fn snippets(mut self, snippets: impl IntoIterator<Item = Snippet>) -> Self {
+        self.snippets.extend(snippets);
+        self
+    }
+
+    pub fn origin(mut self, origin: Location) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    pub fn witnesses(mut self, paths: impl IntoIterator<Item = Vec<String>>) -> Self {
+        self.witnesses.extend(paths);
+        self
+    }
+
+    pub fn panic_kinds(mut self, kinds: impl IntoIterator<Item = PanicKind>) -> Self {
+        self.panic_kinds.extend(kinds);
+        self
+    }
+
+    pub fn note(mut self, message: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            level: Level::Note,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn notes(self, messages: impl IntoIterator<Item = String>) -> Self {
+        messages.into_iter().fold(self, Finding::note)
+    }
+
+    pub fn help(mut self, message: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            level: Level::Help,
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Set the fingerprint from the id, with the kinds of panics if any, and the code at
+    /// primary annotations.
+    pub fn fingerprinted(mut self) -> Self {
+        let mut text = self.id.clone();
+        for snippet in &self.snippets {
+            for annot in snippet.annotations.iter().filter(|a| a.primary) {
+                let code = snippet.source.get(annot.range.clone()).unwrap_or_default();
+                text.push('\n');
+                text.push_str(&code.split_whitespace().collect::<Vec<_>>().join(" "));
+            }
+        }
+        self.fingerprint = format!("{:016x}", fnv1a(text.as_bytes()));
+        self
+    }
+
+    /// Where the finding points at: the first primary annotation, or the origin.
+    pub fn location(&self) -> Option<&Location> {
+        self.snippets
+            .iter()
+            .flat_map(|s| &s.annotations)
+            .find(|a| a.primary)
+            .map(|a| &a.location)
+            .or(self.origin.as_ref())
+    }
+
+    pub fn os_checker(&self) -> OsCheckerFinding {
+        let location = self.location();
+        OsCheckerFinding {
+            file: location.map(|loc| loc.file.clone()),
+            line: location.map(|loc| loc.line),
+            kind: self.check.name().to_owned(),
+            message: self.title.clone(),
+            raw: self.render(&Renderer::plain()),
+        }
+    }
+
+    pub fn render(&self, renderer: &Renderer) -> String {
+        let snippets = self.snippets.iter().map(|s| {
+            annotate::Snippet::source(&*s.source)
+                .path(&*s.file)
+                .line_start(s.line_start)
+                .annotations(s.annotations.iter().map(|a| {
+                    let kind = if a.primary {
+                        annotate::AnnotationKind::Primary
+                    } else {
+                        annotate::AnnotationKind::Context
+                    };
+                    kind.span(a.range.clone()).label(&*a.label)
+                }))
+        });
+        let origin = self.origin.iter().map(|loc| {
+            Origin::path(&*loc.file)
+                .line(loc.line)
+                .char_column(loc.column)
+        });
+        let notes = self
+            .notes
+            .iter()
+            .map(|note| note.level.annotate().message(&*note.message));
+        let fingerprint = format!("Fingerprint: {}", self.fingerprint);
+        let fingerprint =
+            (!self.fingerprint.is_empty()).then(|| annotate::Level::NOTE.message(&*fingerprint));
+
+        let group = self
+            .level
+            .annotate()
+            .primary_title(&*self.title)
+            .elements(snippets)
+            .elements(origin)
+            .elements(notes)
+            .elements(fingerprint);
+        renderer.render(&[group])
+    }
+}
+
+/// Sort findings by location, then by fingerprint, so that outputs don't depend on the
+/// order findings are found in.
+pub fn sort(findings: &mut [Finding]) {
+    findings.sort_by_cached_key(|f| {
+        let loc = f
+            .location()
+            .map(|loc| (loc.file.clone(), loc.line, loc.column));
+        (loc, f.fingerprint.clone())
+    });
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is the same across Rust versions and
+/// platforms.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Level {
+    fn annotate(self) -> annotate::Level<'static> {
+        match self {
+            Level::Error => annotate::Level::ERROR,
+            Level::Warning => annotate::Level::WARNING,
+            Level::Note => annotate::Level::NOTE,
+            Level::Help => annotate::Level::HELP,
+        }
+    }
+}
+
+impl Snippet {
+    pub fn new(file: String, line_start: usize, column_start: usize, source: String) -> Self {
+        Snippet {
+            file,
+            line_start,
+            column_start,
+            source,
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Point at a reported spot.
+    pub fn primary(self, range: Range<usize>, label: impl Into<String>) -> Self {
+        self.annotation(true, range, label.into())
+    }
+
+    pub fn primaries(self, spots: impl IntoIterator<Item = (Range<usize>, String)>) -> Self {
+        spots
+            .into_iter()
+            .fold(self, |s, (range, label)| s.primary(range, label))
+    }
+
+    /// Explain a reported spot.
+    pub fn context(self, range: Range<usize>, label: impl Into<String>) -> Self {
+        self.annotation(false, range, label.into())
+    }
+
+    fn annotation(mut self, primary: bool, range: Range<usize>, label: String) -> Self {
+        let before = &self.source[..range.start];
+        let line = self.line_start + before.matches('\n').count();
+        let column = match before.rfind('\n') {
+            Some(idx) => before[idx + 1..].chars().count() + 1,
+            None => self.column_start + before.chars().count(),
+        };
+        self.annotations.push(Annotation {
+            primary,
+            range,
+            label,
+            location: Location {
+                file: self.file.clone(),
+                line,
+                column,
+            },
+        });
+        self
+    }
+}
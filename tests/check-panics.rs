@@ -3,7 +3,37 @@ fn check() {
     // Pass `BLESS=1` to update stderr.
     let bless = env::var("BLESS").is_ok_and(|x| !x.trim().is_empty());
     // let bless = true;
+    // Graphs written by earlier runs would hide a graph that's no longer written.
+    _ = std::fs::remove_file(GRAPH_DOT);
+    _ = std::fs::remove_file(GRAPH_DROP);
     run_ui_tests(bless);
+    check_graph();
+}
+
+const GRAPH_DOT: &str = "target/ui-graph/graph_dot.dot";
+const GRAPH_DROP: &str = "target/ui-graph/graph_drop.dot";
+
+/// tests/ui/graph-dot.rs writes the subgraph from `api` to panics.
+fn check_graph() {
+    let dot = std::fs::read_to_string(GRAPH_DOT).unwrap();
+    assert!(dot.starts_with("digraph {"), "{dot}");
+    assert!(
+        dot.contains("n0 [label=\"graph_dot::api\" shape=box"),
+        "{dot}"
+    );
+    assert!(
+        dot.contains("n0 -> n1 [label=\"tests/ui/graph-dot.rs:9:5\""),
+        "{dot}"
+    );
+    assert!(dot.contains("fillcolor=red"), "{dot}");
+
+    // tests/ui/graph-drop.rs drops a `Guard` at the end of `scope`.
+    let dot = std::fs::read_to_string(GRAPH_DROP).unwrap();
+    assert!(dot.contains("n1 [label=\"graph_drop::scope\""), "{dot}");
+    assert!(
+        dot.contains("n1 -> n0 [label=\"tests/ui/graph-drop.rs:14:1\" style=dashed"),
+        "{dot}"
+    );
 }
 
 use std::{env, path::PathBuf, sync::LazyLock};
@@ -23,10 +53,7 @@ fn run_ui_tests(bless: bool) {
         ..Default::default()
     };
 
-    config.target_rustcflags = Some(
-        "--crate-type=lib -Zcrate-attr=feature(register_tool) -Zcrate-attr=register_tool(redpen)"
-            .into(),
-    );
+    config.target_rustcflags = Some("--crate-type=lib".into());
 
     config.src_base = "tests/ui".into();
     config.build_base = PROFILE_PATH.join("test/ui");
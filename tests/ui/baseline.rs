@@ -0,0 +1,16 @@
+// compile-flags: --redpen-baseline=tests/ui/baseline.json
+
+// Known in the baseline.
+pub fn known(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+// Known, but calls a new panicking fn, so it's reported again.
+pub fn changed(v: &mut Vec<u32>) {
+    v.push(1);
+    v.insert(0, 1);
+}
+
+pub fn new(v: &mut Vec<u32>) {
+    v.push(1);
+}
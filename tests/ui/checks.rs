@@ -0,0 +1,9 @@
+// compile-flags: --redpen-checks=recursion
+
+// The panic check is not selected, so only the recursion is reported.
+pub fn countdown(v: &mut Vec<u32>, n: u32) {
+    v.push(n);
+    if n > 0 {
+        countdown(v, n - 1)
+    }
+}
@@ -0,0 +1,16 @@
+// compile-flags: --redpen-entries=exports
+
+// Only fns exported by symbol names are reported.
+pub fn not_exported(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+#[no_mangle]
+pub extern "C" fn exported() {
+    not_exported(&mut Vec::new());
+}
+
+#[export_name = "renamed"]
+pub extern "C" fn exported_as() {
+    not_exported(&mut Vec::new());
+}
@@ -0,0 +1,19 @@
+// compile-flags: --redpen-entries=pub --redpen-stack-depth
+
+// Only the public API is reported, not the private helper it calls, and stack depth is
+// only estimated from the public API.
+fn helper(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+pub fn api(v: &mut Vec<u32>) {
+    helper(v);
+}
+
+pub struct S;
+
+impl S {
+    pub fn method(&self, v: &mut Vec<u32>) {
+        helper(v);
+    }
+}
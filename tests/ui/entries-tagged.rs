@@ -0,0 +1,11 @@
+// compile-flags: --redpen-entries=tagged
+
+// Only fns tagged with `#[redpen::entry]` are reported.
+pub fn untagged(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+#[redpen::entry]
+pub fn tagged(v: &mut Vec<u32>) {
+    untagged(v);
+}
@@ -0,0 +1,28 @@
+// compile-flags: --redpen-config=tests/ui/forbid.toml
+
+use std::env;
+use std::process::Command;
+
+pub fn read_home() -> Option<String> {
+    env::var("HOME").ok()
+}
+
+pub fn indirect() -> bool {
+    read_home().is_some()
+}
+
+pub fn spawn() {
+    let _ = Command::new("true").status();
+}
+
+pub fn first(v: &[u8]) -> u8 {
+    *v.first().unwrap()
+}
+
+mod legacy {
+    pub fn load() {}
+}
+
+pub fn load_settings() {
+    legacy::load();
+}
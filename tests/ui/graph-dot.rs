@@ -0,0 +1,10 @@
+// compile-flags: --redpen-checks=recursion --redpen-graph=dot --redpen-graph-entry=graph_dot::api --redpen-graph-dir=target/ui-graph
+
+// The subgraph from `api` is written without a warning.
+fn helper(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+pub fn api(v: &mut Vec<u32>) {
+    helper(v);
+}
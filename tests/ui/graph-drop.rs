@@ -0,0 +1,14 @@
+// compile-flags: --redpen-checks=recursion --redpen-graph=dot --redpen-graph-dir=target/ui-graph
+
+// The drop of `_guard` is an edge of the graph, but not followed by checks.
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        panic!("dropped");
+    }
+}
+
+pub fn scope() {
+    let _guard = Guard;
+}
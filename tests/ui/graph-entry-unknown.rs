@@ -0,0 +1,6 @@
+// compile-flags: --redpen-checks=recursion --redpen-graph=dot --redpen-graph-entry=graph_entry_unknown::missing --redpen-graph-dir=target/ui-graph
+
+// No call graph is written from an entry the crate doesn't have.
+pub fn api(v: &mut Vec<u32>) {
+    v.push(1);
+}
@@ -0,0 +1,22 @@
+// edition:2021
+// compile-flags: --redpen-checks=isr
+
+// Handlers of `no_std` crates acquire locks through `core::cell`.
+#![no_std]
+
+use core::cell::RefCell;
+use core::panic::PanicInfo;
+
+pub struct Counter {
+    ticks: RefCell<u32>,
+}
+
+#[redpen::isr]
+pub fn on_tick(counter: &Counter) {
+    *counter.ticks.borrow_mut() = 1;
+}
+
+#[panic_handler]
+fn handler(_: &PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,25 @@
+// compile-flags: --redpen-config=tests/ui/isr.toml --crate-name=isr
+
+use std::sync::Mutex;
+
+static EVENTS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+pub fn log_message(_: &str) {}
+
+fn flash_write(_: u32) {}
+
+fn record(event: u32) {
+    EVENTS.lock().unwrap().push(event);
+}
+
+#[redpen::isr]
+pub fn on_timer(event: u32) {
+    log_message("timer");
+    record(event);
+}
+
+#[redpen::isr]
+pub fn on_gpio(pin: u32) -> u32 {
+    flash_write(pin);
+    pin.wrapping_mul(2)
+}
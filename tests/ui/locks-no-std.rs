@@ -0,0 +1,30 @@
+// edition:2021
+// compile-flags: --redpen-checks=locks
+
+// RefCell borrows of `no_std` crates go through `core::cell`.
+#![no_std]
+
+use core::cell::RefCell;
+use core::panic::PanicInfo;
+
+pub fn outer(cell: &RefCell<u32>) -> u32 {
+    let value = cell.borrow();
+    bump(cell);
+    *value
+}
+
+fn bump(cell: &RefCell<u32>) {
+    *cell.borrow_mut() = 1;
+}
+
+pub fn released(cell: &RefCell<u32>) {
+    let value = cell.borrow();
+    let copy = *value;
+    core::mem::drop(value);
+    *cell.borrow_mut() = copy;
+}
+
+#[panic_handler]
+fn handler(_: &PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,44 @@
+use std::cell::RefCell;
+use std::sync::{Mutex, RwLock};
+
+pub struct Accounts {
+    balance: Mutex<u64>,
+    log: RwLock<Vec<u64>>,
+}
+
+impl Accounts {
+    pub fn deposit(&self, amount: u64) {
+        let mut balance = self.balance.lock().unwrap();
+        *balance += amount;
+        self.record(amount);
+    }
+
+    fn record(&self, amount: u64) {
+        self.log.write().unwrap().push(amount);
+    }
+
+    pub fn audit(&self) -> u64 {
+        let log = self.log.read().unwrap();
+        let balance = self.balance.lock().unwrap();
+        *balance - log.iter().sum::<u64>()
+    }
+
+    pub fn released(&self) -> u64 {
+        let len = self.log.read().unwrap().len() as u64;
+        *self.balance.lock().unwrap() + len
+    }
+}
+
+pub fn outer(cell: &RefCell<Vec<u8>>) {
+    let items = cell.borrow();
+    inner(cell, items.len());
+}
+
+fn inner(cell: &RefCell<Vec<u8>>, len: usize) {
+    cell.borrow_mut().push(len as u8);
+}
+
+pub fn sequential(cell: &RefCell<Vec<u8>>) {
+    cell.borrow_mut().push(1);
+    inner(cell, 1);
+}
@@ -0,0 +1,22 @@
+// edition:2021
+
+// Panics of `no_std` crates go through `core::panicking::panic_fmt`.
+#![no_std]
+
+use core::panic::PanicInfo;
+
+pub fn checked(n: u32) -> u32 {
+    if n > 100 {
+        panic!("too large: {n}");
+    }
+    n
+}
+
+pub fn halt(info: &PanicInfo) -> ! {
+    handler(info)
+}
+
+#[panic_handler]
+fn handler(_: &PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,5 @@
+// compile-flags: --redpen-format=os-checker
+
+pub fn push(v: &mut Vec<u32>) {
+    v.push(1);
+}
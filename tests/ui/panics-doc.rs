@@ -0,0 +1,64 @@
+// compile-flags: --redpen-checks=panics-doc
+
+/// Pushes to the vector, which panics on capacity overflow, undocumented.
+pub fn push(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+/// Transitively panics through a private helper.
+///
+/// # Panics
+///
+/// Panics if the vector overflows.
+pub fn documented(v: &mut Vec<u32>) {
+    helper(v);
+}
+
+fn helper(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+/// Adds one, without panicking.
+///
+/// # Panics
+///
+/// Used to panic on overflow.
+pub fn stale(x: u32) -> u32 {
+    x.wrapping_add(1)
+}
+
+/// Private fns are not part of the documented API.
+fn private() {
+    panic!()
+}
+
+pub trait Op {
+    fn run(&self);
+}
+
+/// Calls through `dyn`, which the call graph can't follow, so the section is kept.
+///
+/// # Panics
+///
+/// Panics if the operation does.
+pub fn call_dyn(op: &dyn Op) {
+    op.run()
+}
+
+/// Calls through a fn pointer.
+///
+/// # Panics
+///
+/// Panics if `f` does.
+pub fn call_ptr(f: fn()) {
+    f()
+}
+
+pub struct Pusher;
+
+// Trait impl methods are documented by the trait.
+impl Op for Pusher {
+    fn run(&self) {
+        Vec::new().push(1);
+    }
+}
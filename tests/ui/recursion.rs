@@ -0,0 +1,20 @@
+pub fn direct(n: u32) -> u32 {
+    if n == 0 { 0 } else { direct(n - 1) }
+}
+
+pub fn ping(n: u32) {
+    if n > 0 {
+        pong(n - 1)
+    }
+}
+
+fn pong(n: u32) {
+    ping(n)
+}
+
+#[redpen::allow_recursion]
+pub fn bounded(depth: u8) {
+    if depth < 3 {
+        bounded(depth + 1)
+    }
+}
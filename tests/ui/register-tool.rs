@@ -0,0 +1,18 @@
+// The crate registers the tool itself: the attributes injected by redpen are dropped.
+#![feature(register_tool)]
+#![register_tool(redpen)]
+
+#[redpen::silence_panic]
+pub fn silenced(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+#[cfg_attr(redpen, redpen::silence_panic)]
+pub fn silenced_under_cfg(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+#[cfg(not(redpen))]
+pub fn not_analyzed(v: &mut Vec<u32>) {
+    v.push(1);
+}
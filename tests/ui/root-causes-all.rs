@@ -0,0 +1,18 @@
+// compile-flags: --redpen-root-causes
+
+// Every fn is an entry, but only the public ones reaching the helper are listed.
+fn helper(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+fn middle(v: &mut Vec<u32>) {
+    helper(v);
+}
+
+pub fn api(v: &mut Vec<u32>) {
+    middle(v);
+}
+
+fn internal(v: &mut Vec<u32>) {
+    helper(v);
+}
@@ -0,0 +1,29 @@
+// compile-flags: --redpen-root-causes --redpen-entries=pub
+
+// The panic is reported once in the private helper, with the public fns reaching it.
+fn helper(v: &mut Vec<u32>) {
+    v.push(1);
+}
+
+fn middle(v: &mut Vec<u32>) {
+    helper(v);
+}
+
+pub fn api(v: &mut Vec<u32>) {
+    middle(v);
+}
+
+pub fn other(v: &mut Vec<u32>) {
+    helper(v);
+    middle(v);
+}
+
+// A root cause in an entry itself.
+pub fn direct() {
+    panic!("💥")
+}
+
+// Not reachable from any entry.
+fn unused() {
+    panic!()
+}
@@ -0,0 +1,22 @@
+// compile-flags: --test --redpen-skip-tests
+
+// Only the helper is reported, not the test calling it. A method sharing the name of a
+// test is not a test.
+fn helper() -> Vec<u32> {
+    let mut v = Vec::new();
+    v.push(1);
+    v
+}
+
+#[test]
+fn pushes() {
+    assert_eq!(helper().len(), 1);
+}
+
+pub struct Queue(Vec<u32>);
+
+impl Queue {
+    pub fn pushes(&mut self) {
+        self.0.push(1);
+    }
+}
@@ -0,0 +1,22 @@
+// compile-flags: --redpen-stack-depth
+
+pub fn entry(n: u64) -> u64 {
+    let buf = [n; 4];
+    middle(buf[0]) + leaf(n)
+}
+
+fn middle(n: u64) -> u64 {
+    leaf(n) * 2
+}
+
+fn leaf(n: u64) -> u64 {
+    n.wrapping_add(1)
+}
+
+pub fn walk(n: u64) -> u64 {
+    if n == 0 { 0 } else { step(n - 1) }
+}
+
+fn step(n: u64) -> u64 {
+    walk(n.wrapping_sub(1))
+}
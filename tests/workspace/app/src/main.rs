@@ -0,0 +1,4 @@
+fn main() {
+    let v = utils::parse("42");
+    println!("{v}");
+}
@@ -0,0 +1,15 @@
+pub fn parse(s: &str) -> u32 {
+    let mut digits = Vec::new();
+    for c in s.chars() {
+        digits.push(c.to_digit(10).unwrap_or_default());
+    }
+    dep::checked(digits.iter().fold(0, |acc, d| acc * 10 + d))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parses() {
+        assert_eq!(super::parse("42"), 42);
+    }
+}